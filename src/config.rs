@@ -108,7 +108,8 @@ pub fn yaml_to_hash_map(yaml: Yaml) -> Result<Config> {
         .collect::<Result<_>>()
 }
 
-fn get_yaml(config_path: &str) -> Result<Yaml> {
+/// Read a YAML file
+pub fn get_yaml(config_path: &str) -> Result<Yaml> {
     let yaml_str = fs::read_to_string(config_path)
         .chain_err(|| "Failed to read config file")?;
     let mut yaml_list = YamlLoader::load_from_str(&yaml_str)
@@ -11,5 +11,9 @@ error_chain! {
         ConfigError(message: String) {
             display("Error with configuration: {}", message)
         }
+        /// Error in item output markup
+        MarkupError(message: String) {
+            display("Error parsing markup: {}", message)
+        }
     }
 }
@@ -0,0 +1,498 @@
+//! Shows the focused window and current desktop from the root window's
+//! [EWMH](https://specifications.freedesktop.org/wm-spec/latest/) properties,
+//! for window managers other than i3 and sway
+
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+    RETRY_INTERVAL,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask,
+    Window,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["title", "desktop", "desktop_num"];
+
+/// Title of the focused window and the current desktop, updated when the
+/// window manager changes properties on the root window
+pub struct ActiveWindow {
+    /// X display to connect to, or `DISPLAY` if `None`
+    display: Option<String>,
+    format: String,
+    /// Whether to show the panel when switching desktop
+    trigger_show: bool,
+    /// Whether to show the panel when the focused window changes
+    trigger_show_focus: bool,
+    state: Mutex<Option<State>>,
+    text_config: TextConfig,
+}
+
+#[derive(Clone)]
+struct State {
+    /// Focused window, or `None` if no window is focused
+    window: Option<Window>,
+    title: String,
+    desktop: String,
+    desktop_num: Option<u32>,
+}
+
+/// Atoms of the properties that are read
+struct Atoms {
+    net_active_window: Atom,
+    net_wm_name: Atom,
+    net_current_desktop: Atom,
+    net_desktop_names: Atom,
+    utf8_string: Atom,
+}
+
+impl Atoms {
+    fn intern(connection: &RustConnection) -> Result<Self> {
+        let intern = |name: &str| -> Result<Atom> {
+            Ok(connection
+                .intern_atom(false, name.as_bytes())
+                .chain_err(|| "Failed to intern atom")?
+                .reply()
+                .chain_err(|| format!("Failed to intern atom {}", name))?
+                .atom)
+        };
+        Ok(Atoms {
+            net_active_window: intern("_NET_ACTIVE_WINDOW")?,
+            net_wm_name: intern("_NET_WM_NAME")?,
+            net_current_desktop: intern("_NET_CURRENT_DESKTOP")?,
+            net_desktop_names: intern("_NET_DESKTOP_NAMES")?,
+            utf8_string: intern("UTF8_STRING")?,
+        })
+    }
+}
+
+impl ActiveWindow {
+    /// Follow property changes until the connection fails
+    fn follow(
+        &self,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let (connection, screen) = x11rb::connect(self.display.as_deref())
+            .chain_err(|| "Failed to connect to X display")?;
+        let root = connection.setup().roots[screen].root;
+        let atoms = Atoms::intern(&connection)?;
+        select_property_changes(&connection, root)?;
+        info!("Connected to X display");
+
+        let mut active_window = None;
+        loop {
+            // Follow the focused window's title as well as the root window
+            let new_active_window =
+                read_window(&connection, root, atoms.net_active_window);
+            if new_active_window != active_window {
+                if let Some(window) = new_active_window {
+                    // The window may have been destroyed already, in which
+                    // case the error is received as an event and ignored
+                    let _ = select_property_changes(&connection, window);
+                }
+                active_window = new_active_window;
+            }
+
+            let desktop_num =
+                read_u32(&connection, root, atoms.net_current_desktop);
+            let desktop_names =
+                read_string(&connection, root, atoms.net_desktop_names, &atoms);
+            let state = State {
+                window: active_window,
+                title: active_window
+                    .and_then(|window| read_title(&connection, window, &atoms))
+                    .unwrap_or_default(),
+                // Desktop names are null-separated, and default to the
+                // desktop's number counting from one
+                desktop: desktop_num
+                    .map(|num| {
+                        desktop_names
+                            .as_ref()
+                            .and_then(|names| {
+                                names.split('\0').nth(num as usize)
+                            })
+                            .filter(|name| !name.is_empty())
+                            .map(String::from)
+                            .unwrap_or_else(|| (num + 1).to_string())
+                    })
+                    .unwrap_or_default(),
+                desktop_num,
+            };
+
+            let last_state =
+                self.state.lock().unwrap().replace(state.clone());
+            if let Some(last_state) = last_state {
+                let show = (self.trigger_show
+                    && state.desktop_num != last_state.desktop_num)
+                    || (self.trigger_show_focus
+                        && state.window != last_state.window);
+                if show {
+                    window_command_channel.send(window::Command::Show).unwrap();
+                }
+            }
+
+            // Block until a property that's shown changes
+            loop {
+                let event = connection
+                    .wait_for_event()
+                    .chain_err(|| "Lost connection to X display")?;
+                match event {
+                    Event::PropertyNotify(event) => {
+                        let watched = if event.window == root {
+                            [
+                                atoms.net_active_window,
+                                atoms.net_current_desktop,
+                                atoms.net_desktop_names,
+                            ]
+                            .contains(&event.atom)
+                        } else {
+                            Some(event.window) == active_window
+                                && (event.atom == atoms.net_wm_name
+                                    || event.atom
+                                        == Atom::from(AtomEnum::WM_NAME))
+                        };
+                        if watched {
+                            break;
+                        }
+                    }
+                    Event::Error(e) => trace!("Ignoring X error: {:?}", e),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+impl ItemStart for ActiveWindow {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        loop {
+            if let Err(e) = self.follow(&window_command_channel) {
+                warn!("Lost connection to X display: {}", e);
+            }
+            *self.state.lock().unwrap() = None;
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+}
+
+impl TextItem for ActiveWindow {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        let markup = match &*self.state.lock().unwrap() {
+            Some(state) => {
+                let mut values = HashMap::new();
+                values.insert("title", state.title.clone());
+                values.insert("desktop", state.desktop.clone());
+                values.insert(
+                    "desktop_num",
+                    state
+                        .desktop_num
+                        .map(|num| num.to_string())
+                        .unwrap_or_default(),
+                );
+                Markup::parse_or_plain(&util::fill_template(
+                    &self.format,
+                    &values,
+                ))
+            }
+            None => Markup::error("No X display"),
+        };
+        Ok((markup, self.text_config.clone()))
+    }
+}
+
+impl Item for ActiveWindow {}
+
+impl ItemFromConfig for ActiveWindow {
+    fn name() -> &'static str { "active-window" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(display, config, into_string);
+        config_get!(format, config, into_string, "[{desktop}] {title}".into());
+        config_get!(trigger_show, config, as_bool, true);
+        config_get!(trigger_show_focus, config, as_bool, false);
+        util::check_template(&format, FORMAT_KEYS)?;
+
+        Ok(Box::new(ActiveWindow {
+            display,
+            format,
+            trigger_show,
+            trigger_show_focus,
+            state: Mutex::new(None),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Receive `PropertyNotify` events for a window
+fn select_property_changes(
+    connection: &RustConnection,
+    window: Window,
+) -> Result<()>
+{
+    connection
+        .change_window_attributes(
+            window,
+            &ChangeWindowAttributesAux::new()
+                .event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .and_then(|_| connection.flush())
+        .chain_err(|| "Failed to select property changes")
+}
+
+/// Read a property's value, or `None` if it's not set or the window doesn't
+/// exist
+fn read_property(
+    connection: &RustConnection,
+    window: Window,
+    property: Atom,
+) -> Option<(Atom, Vec<u8>, u8)>
+{
+    let reply = connection
+        .get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX)
+        .ok()?
+        .reply()
+        .ok()?;
+    if reply.type_ == Atom::from(AtomEnum::NONE) {
+        return None;
+    }
+    Some((reply.type_, reply.value, reply.format))
+}
+
+/// Read a property holding a single 32-bit value, such as a window or number.
+/// A window of zero means no window
+fn read_u32(
+    connection: &RustConnection,
+    window: Window,
+    property: Atom,
+) -> Option<u32>
+{
+    let (_, value, format) = read_property(connection, window, property)?;
+    if format != 32 || value.len() < 4 {
+        return None;
+    }
+    Some(u32::from_ne_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+/// Read the window in a property, e.g. `_NET_ACTIVE_WINDOW`
+fn read_window(
+    connection: &RustConnection,
+    window: Window,
+    property: Atom,
+) -> Option<Window>
+{
+    read_u32(connection, window, property).filter(|window| *window != 0)
+}
+
+/// Read a window's title, falling back to the ICCCM `WM_NAME` for windows
+/// that don't set `_NET_WM_NAME`
+fn read_title(
+    connection: &RustConnection,
+    window: Window,
+    atoms: &Atoms,
+) -> Option<String>
+{
+    read_string(connection, window, atoms.net_wm_name, atoms).or_else(|| {
+        read_string(connection, window, AtomEnum::WM_NAME.into(), atoms)
+    })
+}
+
+/// Read a text property, decoding `STRING` properties as Latin-1
+fn read_string(
+    connection: &RustConnection,
+    window: Window,
+    property: Atom,
+    atoms: &Atoms,
+) -> Option<String>
+{
+    let (type_, value, _) = read_property(connection, window, property)?;
+    if type_ == atoms.utf8_string {
+        Some(String::from_utf8_lossy(&value).into_owned())
+    } else if type_ == Atom::from(AtomEnum::STRING) {
+        Some(value.iter().map(|byte| char::from(*byte)).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+
+    /// X server that's killed when dropped
+    struct Xvfb(Child);
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    /// Start Xvfb on a free display, returning the display's name, or `None`
+    /// if Xvfb isn't installed
+    fn start_xvfb() -> Option<(Xvfb, String)> {
+        // Xvfb writes the display number it chose to the given descriptor
+        let mut child = Command::new("Xvfb")
+            .args(["-displayfd", "1", "-nolisten", "tcp"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        Some((Xvfb(child), format!(":{}", line.trim())))
+    }
+
+    /// Create a window with a title
+    fn create_window(
+        connection: &RustConnection,
+        root: Window,
+        atoms: &Atoms,
+        title: &str,
+    ) -> Window
+    {
+        let window = connection.generate_id().unwrap();
+        connection
+            .create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                0,
+                &CreateWindowAux::new(),
+            )
+            .unwrap();
+        set_title(connection, window, atoms, title);
+        window
+    }
+
+    fn set_title(
+        connection: &RustConnection,
+        window: Window,
+        atoms: &Atoms,
+        title: &str,
+    )
+    {
+        connection
+            .change_property8(
+                PropMode::REPLACE,
+                window,
+                atoms.net_wm_name,
+                atoms.utf8_string,
+                title.as_bytes(),
+            )
+            .unwrap();
+        connection.flush().unwrap();
+    }
+
+    /// Set a 32-bit property on the root window, as a window manager does
+    fn set_root_u32(
+        connection: &RustConnection,
+        root: Window,
+        property: Atom,
+        type_: AtomEnum,
+        value: u32,
+    )
+    {
+        connection
+            .change_property32(PropMode::REPLACE, root, property, type_, &[
+                value,
+            ])
+            .unwrap();
+        connection.flush().unwrap();
+    }
+
+    /// Wait until the item shows a window's title
+    fn wait_for_title(item: &ActiveWindow, title: &str) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let state = item.state.lock().unwrap().clone();
+            if state.map(|state| state.title).as_deref() == Some(title) {
+                return;
+            }
+            assert!(Instant::now() < deadline, "Timed out waiting for title");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn focus_changes_show_panel() {
+        let (_xvfb, display) = match start_xvfb() {
+            Some(xvfb) => xvfb,
+            None => {
+                eprintln!("Skipping test, Xvfb is not installed");
+                return;
+            }
+        };
+        let (connection, screen) = x11rb::connect(Some(&display)).unwrap();
+        let root = connection.setup().roots[screen].root;
+        let atoms = Atoms::intern(&connection).unwrap();
+        // Windows with the same title, so that only the focus tells them
+        // apart
+        let first = create_window(&connection, root, &atoms, "Terminal");
+        let second = create_window(&connection, root, &atoms, "Terminal");
+        let active = atoms.net_active_window;
+        set_root_u32(&connection, root, active, AtomEnum::WINDOW, first);
+        let desktop = atoms.net_current_desktop;
+        set_root_u32(&connection, root, desktop, AtomEnum::CARDINAL, 0);
+
+        let item = Arc::new(ActiveWindow {
+            display: Some(display),
+            format: "{title}".into(),
+            trigger_show: false,
+            trigger_show_focus: true,
+            state: Mutex::new(None),
+            text_config: TextConfig::parse(&mut Config::new()).unwrap(),
+        });
+        let (window_command_channel, window_commands) = mpsc::channel();
+        let started = item.clone();
+        thread::spawn(move || started.start(window_command_channel));
+        wait_for_title(&item, "Terminal");
+
+        // Focusing another window shows the panel even though the title is
+        // the same
+        set_root_u32(&connection, root, active, AtomEnum::WINDOW, second);
+        let command = window_commands.recv_timeout(Duration::from_secs(5));
+        assert!(matches!(command, Ok(window::Command::Show)));
+
+        // Retitling the focused window updates the text without showing the
+        // panel
+        set_title(&connection, second, &atoms, "Editor");
+        wait_for_title(&item, "Editor");
+        let command = window_commands.recv_timeout(Duration::from_millis(200));
+        assert!(command.is_err());
+    }
+}
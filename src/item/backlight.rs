@@ -0,0 +1,185 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+    RETRY_INTERVAL,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use inotify::{EventMask, Inotify, WatchMask};
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["device", "percent", "brightness", "max"];
+
+/// Backlight brightness, read from `/sys/class/backlight`. Changes are
+/// watched for with inotify, so the panel can be shown when the brightness
+/// changes
+pub struct Backlight {
+    root_path: PathBuf,
+    /// Name of the device to show, or the first device found if `None`
+    device: Option<String>,
+    format: String,
+    trigger_show: bool,
+    last_brightness: Mutex<Option<u64>>,
+    text: Mutex<Markup>,
+    text_config: TextConfig,
+}
+
+impl Backlight {
+    fn device_path(&self) -> Result<PathBuf> {
+        if let Some(device) = &self.device {
+            return Ok(self.root_path.join(device));
+        }
+        let mut device_paths = fs::read_dir(&self.root_path)
+            .chain_err(|| {
+                format!("Failed to read {}", self.root_path.display())
+            })?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        device_paths.sort();
+        device_paths.into_iter().next().chain_err(|| {
+            format!("No backlight found in {}", self.root_path.display())
+        })
+    }
+
+    /// Read the brightness and update the text. Returns whether the
+    /// brightness changed since the last update
+    fn update(&self, device_path: &Path) -> Result<bool> {
+        let read_number = |file: &str| -> Result<u64> {
+            let path = device_path.join(file);
+            util::read_trimmed(&path)?.parse().chain_err(|| {
+                format!("Invalid brightness in {}", path.display())
+            })
+        };
+        let brightness = read_number("brightness")?;
+        let max = read_number("max_brightness")?;
+        trace!("Updating backlight: {}/{}", brightness, max);
+
+        let mut values = HashMap::new();
+        values.insert(
+            "device",
+            device_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        );
+        let percent = if max == 0 {
+            0.0
+        } else {
+            brightness as f64 / max as f64 * 100.0
+        };
+        values.insert("percent", format!("{:.0}", percent));
+        values.insert("brightness", brightness.to_string());
+        values.insert("max", max.to_string());
+        *self.text.lock().unwrap() =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+
+        let mut last_brightness = self.last_brightness.lock().unwrap();
+        let changed =
+            matches!(*last_brightness, Some(last) if last != brightness);
+        *last_brightness = Some(brightness);
+        Ok(changed)
+    }
+
+    /// Watch the brightness of a device until the device is removed
+    fn watch(
+        &self,
+        inotify: &mut Inotify,
+        device_path: &Path,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let brightness_path = device_path.join("brightness");
+        inotify
+            .watches()
+            .add(&brightness_path, WatchMask::MODIFY)
+            .chain_err(|| {
+                format!("Failed to watch {}", brightness_path.display())
+            })?;
+        self.update(device_path)?;
+
+        let mut buffer = [0; 1024];
+        loop {
+            let events = inotify
+                .read_events_blocking(&mut buffer)
+                .chain_err(|| "Failed to read inotify events")?
+                .collect::<Vec<_>>();
+            if events
+                .iter()
+                .any(|event| event.mask.contains(EventMask::IGNORED))
+            {
+                debug!("Backlight {} was removed", device_path.display());
+                return Ok(());
+            }
+            if self.update(device_path)? && self.trigger_show {
+                window_command_channel.send(window::Command::Show).unwrap();
+            }
+        }
+    }
+}
+
+impl ItemStart for Backlight {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let mut inotify =
+            Inotify::init().chain_err(|| "Failed to initialise inotify")?;
+        loop {
+            let result = self.device_path().and_then(|device_path| {
+                self.watch(&mut inotify, &device_path, &window_command_channel)
+            });
+            if let Err(e) = result {
+                warn!("Failed to read backlight: {}", e);
+                *self.text.lock().unwrap() = Markup::error("No backlight");
+                thread::sleep(RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+impl TextItem for Backlight {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok((self.text.lock().unwrap().clone(), self.text_config.clone()))
+    }
+}
+
+impl Item for Backlight {}
+
+impl ItemFromConfig for Backlight {
+    fn name() -> &'static str { "backlight" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(
+            root_path,
+            config,
+            into_string,
+            "/sys/class/backlight".into()
+        );
+        config_get!(device, config, into_string);
+        config_get!(format, config, into_string, "{percent}%".into());
+        // Showing the panel on changes is the point of the item, so it's on
+        // by default
+        config_get!(trigger_show, config, as_bool, true);
+        util::check_template(&format, FORMAT_KEYS)?;
+
+        Ok(Box::new(Backlight {
+            root_path: root_path.into(),
+            device,
+            format,
+            trigger_show,
+            last_brightness: Mutex::new(None),
+            text: Mutex::new(Markup::default()),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
@@ -0,0 +1,445 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, Markup, PulledItem, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["name", "capacity", "status", "time", "icon"];
+
+/// Battery showing its charge, read from `/sys/class/power_supply`
+pub struct Battery {
+    root_path: PathBuf,
+    /// Name of the battery to show, or the first battery found if `None`
+    battery: Option<String>,
+    format: String,
+    interval: Duration,
+    /// Percentages to show the panel at when the charge falls past them
+    low_thresholds: Vec<f64>,
+    trigger_show: bool,
+    /// Whether to show the panel when AC is plugged or unplugged
+    trigger_show_ac: bool,
+    last_state: Mutex<Option<BatteryState>>,
+    text: Mutex<Markup>,
+    text_config: TextConfig,
+}
+
+/// A reading of the battery
+#[derive(Clone)]
+struct BatteryState {
+    name: String,
+    /// Charge as a percentage
+    capacity: f64,
+    /// E.g. "Charging", "Discharging" or "Full"
+    status: String,
+    /// Time until the battery is empty when discharging, or full when
+    /// charging
+    remaining: Option<Duration>,
+    ac_online: bool,
+}
+
+impl Battery {
+    fn read_state(&self) -> Result<BatteryState> {
+        let supplies = power_supplies(&self.root_path)?;
+        let battery_path = supplies
+            .iter()
+            .filter(|(_, supply_type)| supply_type == "Battery")
+            .map(|(path, _)| path)
+            .find(|path| match &self.battery {
+                Some(battery) => path.ends_with(battery),
+                None => true,
+            })
+            .chain_err(|| {
+                format!("No battery found in {}", self.root_path.display())
+            })?;
+        let read = |file: &str| util::read_trimmed(battery_path.join(file));
+        let read_number = |file: &str| -> Option<f64> {
+            read(file).ok().and_then(|value| value.parse().ok())
+        };
+
+        let status = read("status")?;
+        // Batteries report either energy in µWh and power in µW, or charge in
+        // µAh and current in µA
+        let (now, full, rate) = match read_number("energy_now") {
+            Some(energy) => (
+                Some(energy),
+                read_number("energy_full"),
+                read_number("power_now"),
+            ),
+            None => (
+                read_number("charge_now"),
+                read_number("charge_full"),
+                read_number("current_now"),
+            ),
+        };
+        let capacity = match (read_number("capacity"), now, full) {
+            (Some(capacity), _, _) => capacity,
+            (None, Some(now), Some(full)) if full > 0.0 => now / full * 100.0,
+            _ => bail!(ErrorKind::CommandError(format!(
+                "Failed to read capacity of {}",
+                battery_path.display()
+            ))),
+        };
+        let hours_remaining = match (status.as_str(), now, full, rate) {
+            (_, _, _, Some(rate)) if rate <= 0.0 => None,
+            ("Discharging", Some(now), _, Some(rate)) => Some(now / rate),
+            ("Charging", Some(now), Some(full), Some(rate)) => {
+                Some((full - now).max(0.0) / rate)
+            }
+            _ => None,
+        };
+
+        // Fall back to the battery's status if there's no AC adapter
+        let mut adapters = supplies
+            .iter()
+            .filter(|(_, supply_type)| supply_type == "Mains")
+            .peekable();
+        let ac_online = if adapters.peek().is_some() {
+            adapters.any(|(path, _)| {
+                util::read_trimmed(path.join("online"))
+                    .map(|online| online == "1")
+                    .unwrap_or(false)
+            })
+        } else {
+            status != "Discharging"
+        };
+
+        Ok(BatteryState {
+            name: battery_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            capacity,
+            status,
+            remaining: hours_remaining
+                .map(|hours| Duration::from_secs((hours * 3600.0) as u64)),
+            ac_online,
+        })
+    }
+
+    /// Whether the panel should be shown after going from `last` to `state`
+    fn should_show(
+        &self,
+        last: Option<&BatteryState>,
+        state: &BatteryState,
+    ) -> bool
+    {
+        let last = match last {
+            Some(last) => last,
+            None => return self.trigger_show,
+        };
+        let crossed_threshold = self.low_thresholds.iter().any(|threshold| {
+            last.capacity > *threshold && state.capacity <= *threshold
+        });
+        self.trigger_show
+            || crossed_threshold
+            || (self.trigger_show_ac && last.ac_online != state.ac_online)
+    }
+
+    fn format_state(&self, state: &BatteryState) -> Markup {
+        let mut values = HashMap::new();
+        values.insert("name", state.name.clone());
+        values.insert("capacity", format!("{:.0}", state.capacity));
+        values.insert("status", state.status.clone());
+        values.insert(
+            "time",
+            state
+                .remaining
+                .map(|remaining| {
+                    let minutes = remaining.as_secs() / 60;
+                    format!("{}:{:02}", minutes / 60, minutes % 60)
+                })
+                .unwrap_or_default(),
+        );
+        values.insert("icon", icon_name(state.capacity).into());
+        let markup =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+
+        // Highlight the battery when it's below the lowest threshold
+        let is_low = self
+            .low_thresholds
+            .iter()
+            .any(|threshold| state.capacity <= *threshold);
+        if is_low && !state.ac_online {
+            markup.urgent()
+        } else {
+            markup
+        }
+    }
+}
+
+impl PulledItem for Battery {
+    fn pull(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let state = match self.read_state() {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to read battery: {}", e);
+                *self.text.lock().unwrap() = Markup::error("No battery");
+                return Ok(());
+            }
+        };
+        trace!("Updating battery: {}%", state.capacity);
+
+        let mut last_state = self.last_state.lock().unwrap();
+        let show = self.should_show(last_state.as_ref(), &state);
+        *self.text.lock().unwrap() = self.format_state(&state);
+        *last_state = Some(state);
+        if show {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+        Ok(())
+    }
+
+    fn get_interval(&self) -> Duration { self.interval }
+}
+
+impl TextItem for Battery {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok((self.text.lock().unwrap().clone(), self.text_config.clone()))
+    }
+}
+
+impl Item for Battery {}
+
+impl ItemFromConfig for Battery {
+    fn name() -> &'static str { "battery" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(
+            root_path,
+            config,
+            into_string,
+            "/sys/class/power_supply".into()
+        );
+        config_get!(battery, config, into_string);
+        config_get!(format, config, into_string, "{capacity}%".into());
+        config_get!(interval_sec, config, as_f64, 10.0);
+        config_get!(low_thresholds, config, as_i64, list);
+        config_get!(trigger_show, config, as_bool, false);
+        config_get!(trigger_show_ac, config, as_bool, false);
+        util::check_template(&format, FORMAT_KEYS)?;
+
+        Ok(Box::new(Battery {
+            root_path: root_path.into(),
+            battery,
+            format,
+            interval: util::duration_from_config(
+                "interval-sec",
+                interval_sec,
+            )?,
+            low_thresholds: low_thresholds
+                .into_iter()
+                .map(|threshold: i64| threshold as f64)
+                .collect(),
+            trigger_show,
+            trigger_show_ac,
+            last_state: Mutex::new(None),
+            text: Mutex::new(Markup::default()),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Get the paths and types of the power supplies in `root_path`, sorted by
+/// name
+fn power_supplies(root_path: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut supplies = fs::read_dir(root_path)
+        .chain_err(|| format!("Failed to read {}", root_path.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let supply_type = util::read_trimmed(path.join("type")).ok()?;
+            Some((path, supply_type))
+        })
+        .collect::<Vec<_>>();
+    supplies.sort();
+    Ok(supplies)
+}
+
+/// Get the name of the FontAwesome battery icon for a charge percentage
+fn icon_name(capacity: f64) -> &'static str {
+    match capacity {
+        c if c >= 87.5 => "battery-full",
+        c if c >= 62.5 => "battery-three-quarters",
+        c if c >= 37.5 => "battery-half",
+        c if c >= 12.5 => "battery-quarter",
+        _ => "battery-empty",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    /// Write the files of a power supply in `root`
+    fn write_supply(root: &TempDir, name: &str, files: &[(&str, &str)]) {
+        let path = root.path().join(name);
+        fs::create_dir_all(&path).unwrap();
+        for (file, contents) in files {
+            fs::write(path.join(file), format!("{}\n", contents)).unwrap();
+        }
+    }
+
+    fn battery(root: &TempDir) -> Battery {
+        Battery {
+            root_path: root.path().into(),
+            battery: None,
+            format: "{capacity}%".into(),
+            interval: Duration::from_secs(10),
+            low_thresholds: vec![20.0, 5.0],
+            trigger_show: false,
+            trigger_show_ac: true,
+            last_state: Mutex::new(None),
+            text: Mutex::new(Markup::default()),
+            text_config: TextConfig::parse(&mut Config::new()).unwrap(),
+        }
+    }
+
+    fn state(capacity: f64, ac_online: bool) -> BatteryState {
+        BatteryState {
+            name: "BAT0".into(),
+            capacity,
+            status: "Discharging".into(),
+            remaining: None,
+            ac_online,
+        }
+    }
+
+    #[test]
+    fn read_state_uses_energy() {
+        let root = TempDir::new().unwrap();
+        write_supply(&root, "BAT0", &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("energy_now", "25000000"),
+            ("energy_full", "50000000"),
+            ("power_now", "10000000"),
+        ]);
+        let state = battery(&root).read_state().unwrap();
+        assert_eq!(state.name, "BAT0");
+        assert_eq!(state.capacity, 50.0);
+        assert_eq!(state.remaining, Some(Duration::from_secs(9000)));
+        // Without an adapter, AC is found from the battery's status
+        assert!(!state.ac_online);
+    }
+
+    #[test]
+    fn read_state_uses_charge_and_capacity() {
+        let root = TempDir::new().unwrap();
+        write_supply(&root, "BAT1", &[
+            ("type", "Battery"),
+            ("status", "Charging"),
+            ("capacity", "40"),
+            ("charge_now", "2000000"),
+            ("charge_full", "5000000"),
+            ("current_now", "1000000"),
+        ]);
+        let state = battery(&root).read_state().unwrap();
+        assert_eq!(state.capacity, 40.0);
+        assert_eq!(state.remaining, Some(Duration::from_secs(3 * 3600)));
+        assert!(state.ac_online);
+    }
+
+    #[test]
+    fn read_state_has_no_time_without_rate() {
+        let root = TempDir::new().unwrap();
+        write_supply(&root, "BAT0", &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("energy_now", "25000000"),
+            ("energy_full", "50000000"),
+            ("power_now", "0"),
+        ]);
+        assert_eq!(battery(&root).read_state().unwrap().remaining, None);
+    }
+
+    #[test]
+    fn read_state_reads_adapters() {
+        let root = TempDir::new().unwrap();
+        write_supply(&root, "AC", &[("type", "Mains"), ("online", "0")]);
+        write_supply(&root, "BAT0", &[
+            ("type", "Battery"),
+            ("status", "Not charging"),
+            ("capacity", "80"),
+        ]);
+        let battery = battery(&root);
+        assert!(!battery.read_state().unwrap().ac_online);
+
+        write_supply(&root, "AC", &[("online", "1")]);
+        assert!(battery.read_state().unwrap().ac_online);
+    }
+
+    #[test]
+    fn read_state_fails_without_battery() {
+        let root = TempDir::new().unwrap();
+        write_supply(&root, "AC", &[("type", "Mains"), ("online", "1")]);
+        assert!(battery(&root).read_state().is_err());
+    }
+
+    #[test]
+    fn should_show_when_crossing_low_threshold() {
+        let root = TempDir::new().unwrap();
+        let battery = battery(&root);
+        let show = |last: f64, capacity: f64| {
+            battery.should_show(
+                Some(&state(last, false)),
+                &state(capacity, false),
+            )
+        };
+        assert!(show(21.0, 20.0));
+        assert!(show(6.0, 4.0));
+        assert!(!show(20.0, 19.0));
+        assert!(!show(30.0, 25.0));
+        // Charging back past a threshold isn't announced
+        assert!(!show(19.0, 21.0));
+        assert!(!battery.should_show(None, &state(3.0, false)));
+    }
+
+    #[test]
+    fn should_show_when_ac_changes() {
+        let root = TempDir::new().unwrap();
+        let mut battery = battery(&root);
+        let plugged = state(50.0, true);
+        let unplugged = state(50.0, false);
+        assert!(battery.should_show(Some(&unplugged), &plugged));
+        assert!(battery.should_show(Some(&plugged), &unplugged));
+        assert!(!battery.should_show(Some(&plugged), &plugged));
+
+        battery.trigger_show_ac = false;
+        assert!(!battery.should_show(Some(&unplugged), &plugged));
+    }
+
+    #[test]
+    fn pull_shows_panel_when_ac_is_unplugged() {
+        let root = TempDir::new().unwrap();
+        write_supply(&root, "AC", &[("type", "Mains"), ("online", "1")]);
+        write_supply(&root, "BAT0", &[
+            ("type", "Battery"),
+            ("status", "Charging"),
+            ("capacity", "80"),
+        ]);
+        let battery = battery(&root);
+        let (window_command_channel, window_commands) = mpsc::channel();
+        battery.pull(window_command_channel.clone()).unwrap();
+        battery.pull(window_command_channel.clone()).unwrap();
+        assert_eq!(window_commands.try_iter().count(), 0);
+
+        write_supply(&root, "AC", &[("online", "0")]);
+        battery.pull(window_command_channel).unwrap();
+        assert_eq!(window_commands.try_iter().count(), 1);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, Markup, PulledItem, TextConfig, TextItem,
+};
+use crate::window;
+
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use chrono::format::{Fixed, Item as FormatItem, Numeric, StrftimeItems};
+use chrono::{Local, Utc};
+use chrono_tz::Tz;
+
+/// Clock showing the current time
+pub struct Clock {
+    format: String,
+    timezone: Option<Tz>,
+    /// Seconds between updates, either a second or a minute
+    period_sec: u64,
+    trigger_show: bool,
+    text: Mutex<Markup>,
+    text_config: TextConfig,
+}
+
+impl Clock {
+    fn format_now(&self) -> String {
+        match self.timezone {
+            Some(timezone) => {
+                Utc::now().with_timezone(&timezone).format(&self.format)
+            }
+            None => Local::now().format(&self.format),
+        }
+        .to_string()
+    }
+}
+
+impl PulledItem for Clock {
+    fn pull(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let text = self.format_now();
+        trace!("Updating clock: {}", text);
+        *self.text.lock().unwrap() = Markup::parse_or_plain(&text);
+        if self.trigger_show {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+        Ok(())
+    }
+
+    /// Get the time until the next second or minute boundary, so that the
+    /// clock changes at the same time as the wall clock
+    fn get_interval(&self) -> Duration {
+        let now = Utc::now();
+        let period_nanos = self.period_sec * 1_000_000_000;
+        let elapsed_nanos = (now.timestamp() as u64 % self.period_sec)
+            * 1_000_000_000
+            + u64::from(now.timestamp_subsec_nanos());
+        Duration::from_nanos(period_nanos - elapsed_nanos)
+    }
+}
+
+impl TextItem for Clock {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok((self.text.lock().unwrap().clone(), self.text_config.clone()))
+    }
+}
+
+impl Item for Clock {}
+
+impl ItemFromConfig for Clock {
+    fn name() -> &'static str { "clock" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(format, config, into_string, "%H:%M".into());
+        config_get!(timezone, config, into_string);
+        config_get!(trigger_show, config, as_bool, false);
+
+        // Check the format now, as formatting panics on invalid formats
+        ensure!(
+            StrftimeItems::new(&format).all(|i| i != FormatItem::Error),
+            ErrorKind::ConfigError(format!("Invalid clock format: {}", format))
+        );
+        let timezone = match timezone {
+            Some(timezone) => Some(timezone.parse::<Tz>().map_err(|_| {
+                ErrorKind::ConfigError(format!(
+                    "Unrecognized timezone: {}",
+                    timezone
+                ))
+            })?),
+            None => None,
+        };
+        let period_sec = if shows_seconds(&format) { 1 } else { 60 };
+
+        Ok(Box::new(Clock {
+            format,
+            timezone,
+            period_sec,
+            trigger_show,
+            text: Mutex::new(Markup::default()),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Whether the format includes seconds, so the clock needs updating every
+/// second
+fn shows_seconds(format: &str) -> bool {
+    StrftimeItems::new(format).any(|item| {
+        matches!(
+            item,
+            FormatItem::Numeric(Numeric::Second, _)
+                | FormatItem::Numeric(Numeric::Timestamp, _)
+                | FormatItem::Fixed(Fixed::RFC2822)
+                | FormatItem::Fixed(Fixed::RFC3339)
+        )
+    })
+}
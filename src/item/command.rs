@@ -5,16 +5,81 @@ use std::time::Duration;
 
 use crate::config::Config;
 use crate::error::*;
+use crate::item::i3bar::{self, Block, ClickWriter};
+use crate::item::json_output;
 use crate::item::PulledItem;
-use crate::item::{Item, ItemFromConfig, ItemStart, TextConfig, TextItem};
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+};
 use crate::window;
 
 /// A command that can be run
 pub struct Command {
     command_list: Vec<String>,
-    command_output: Arc<Mutex<String>>,
+    command_output: Arc<Mutex<Markup>>,
     trigger_show: bool,
     text_config: TextConfig,
+    protocol: Protocol,
+    output_format: OutputFormat,
+    /// Blocks from the last status line, if the command uses the i3bar
+    /// protocol
+    blocks: Arc<Mutex<Vec<Block>>>,
+    /// Writes click events, if the command uses the i3bar protocol and
+    /// accepts them
+    click_writer: Arc<Mutex<Option<ClickWriter>>>,
+}
+
+/// The format of each update a command outputs
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Text with XML markup
+    Markup,
+    /// A JSON object, setting the text and how it is displayed
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "markup" => OutputFormat::Markup,
+            "json" => OutputFormat::Json,
+            s => {
+                return Err(ErrorKind::ConfigError(format!(
+                    "Unrecognized output format: {}",
+                    s
+                ))
+                .into());
+            }
+        })
+    }
+}
+
+/// The protocol a command uses to write output
+#[derive(Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// Each line (or the whole output, for pulled commands) is an update
+    /// in the command's output format
+    Plain,
+    /// The i3bar JSON protocol
+    I3bar,
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "plain" => Protocol::Plain,
+            "i3bar" => Protocol::I3bar,
+            s => {
+                return Err(ErrorKind::ConfigError(format!(
+                    "Unrecognized protocol: {}",
+                    s
+                ))
+                .into());
+            }
+        })
+    }
 }
 
 impl Command {
@@ -27,20 +92,34 @@ impl Command {
     {
         Command {
             command_list,
-            command_output: Arc::new(Mutex::new(String::new())),
+            command_output: Arc::new(Mutex::new(Markup::default())),
             trigger_show,
             text_config,
+            protocol: Protocol::Plain,
+            output_format: OutputFormat::Markup,
+            blocks: Arc::new(Mutex::new(vec![])),
+            click_writer: Arc::new(Mutex::new(None)),
         }
     }
 
     #[allow(missing_docs)]
     fn parse(config: &mut Config) -> Result<Self> {
+        config_get!(trigger_show, config, as_bool, false);
+        config_get!(output_format, config, into_string, "markup".into());
+        let command_list = Command::parse_command_list(config)?;
+        let text_config = TextConfig::parse(config)?;
+        let mut command = Command::new(command_list, trigger_show, text_config);
+        command.output_format = output_format.parse()?;
+        Ok(command)
+    }
+
+    /// Get the command to run from either a command list, or an interpreter
+    /// and a script
+    fn parse_command_list(config: &mut Config) -> Result<Vec<String>> {
         config_get!(command, config, into_string, list);
         config_get!(interpreter, config, into_string);
         config_get!(script, config, into_string);
         config_get!(script_path, config, into_string);
-        config_get!(trigger_show, config, as_bool, false);
-        let text_config = TextConfig::parse(config)?;
 
         if !command.is_empty() {
             if interpreter.is_some()
@@ -54,7 +133,7 @@ impl Command {
                 ));
             }
 
-            return Ok(Command::new(command, trigger_show, text_config));
+            return Ok(command);
         }
 
         if let Some(interpreter) = interpreter {
@@ -65,19 +144,45 @@ impl Command {
             }
 
             if let Some(script_path) = script_path {
-                let command = vec![interpreter, script_path];
-                return Ok(Command::new(command, trigger_show, text_config));
+                return Ok(vec![interpreter, script_path]);
             }
 
             if let Some(script) = script {
-                let command = vec![interpreter, "-c".into(), script];
-                return Ok(Command::new(command, trigger_show, text_config));
+                return Ok(vec![interpreter, "-c".into(), script]);
             }
         }
 
         bail!(ErrorKind::ConfigError("No command specified".into()));
     }
 
+    /// Set the output of the command, and show the window if needed
+    fn set_output(
+        &self,
+        output: &str,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    )
+    {
+        let (markup, show) = match self.output_format {
+            OutputFormat::Markup => {
+                (Markup::parse_or_plain(output), self.trigger_show)
+            }
+            OutputFormat::Json => match json_output::parse_update(output) {
+                Ok(update) => (
+                    update.markup,
+                    update.show.unwrap_or(self.trigger_show) || update.urgent,
+                ),
+                Err(err) => {
+                    warn!("Skipping command output: {}", err);
+                    return;
+                }
+            },
+        };
+        *self.command_output.lock().unwrap() = markup;
+        if show {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+    }
+
     /// Create a `std::process::Command` struct from a command list
     fn create_command(command_list: Vec<String>) -> Result<process::Command> {
         ensure!(
@@ -92,13 +197,31 @@ impl Command {
 }
 
 impl TextItem for Command {
-    fn get_text(&self) -> Result<(String, TextConfig)> {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
         // TODO: Get rid of clone
         Ok((
-            self.command_output.lock().unwrap().trim().into(),
+            self.command_output.lock().unwrap().clone(),
             self.text_config.clone(),
         ))
     }
+
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        if self.protocol != Protocol::I3bar {
+            return Ok(vec![self.get_text()?]);
+        }
+        Ok(self
+            .blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|block| {
+                (
+                    block.markup.clone(),
+                    self.text_config.with_min_width(block.min_width.clone()),
+                )
+            })
+            .collect())
+    }
 }
 
 /// Command that can be pulled at an interval
@@ -118,12 +241,10 @@ impl PulledItem for PulledCommand {
             Command::create_command(self.command.command_list.clone())?;
         let output =
             command.output().chain_err(|| "Failed to execute command")?;
-        *self.command.command_output.lock().unwrap() =
-            String::from_utf8(output.stdout)
-                .chain_err(|| "Failed to decode bytes into utf8 string")?;
-        if self.command.trigger_show {
-            window_command_channel.send(window::Command::Show).unwrap();
-        }
+        let output = String::from_utf8(output.stdout)
+            .chain_err(|| "Failed to decode bytes into utf8 string")?;
+        self.command
+            .set_output(output.trim_end(), &window_command_channel);
         Ok(())
     }
 
@@ -131,7 +252,7 @@ impl PulledItem for PulledCommand {
 }
 
 impl TextItem for PulledCommand {
-    fn get_text(&self) -> Result<(String, TextConfig)> {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
         self.command.get_text()
     }
 }
@@ -161,28 +282,111 @@ impl ItemStart for PushedCommand {
     {
         loop {
             debug!("Starting pushed command {:?}", self.command_list);
-            let command = Command::create_command(self.command_list.clone())?
-                .spawn()
-                .chain_err(|| "Failed to start command")?;
-            let stdout = BufReader::new(command.stdout.unwrap());
-            for line in stdout.lines() {
-                let line = line.chain_err(|| "Failed to read line")?;
-                trace!("Got output from pushed command: {}", line);
-                *self.command_output.lock().unwrap() = line;
-                if self.trigger_show {
-                    window_command_channel.send(window::Command::Show).unwrap();
+            let mut command =
+                Command::create_command(self.command_list.clone())?;
+            if self.protocol == Protocol::I3bar {
+                // Keep stdin open for sending click events
+                command.stdin(process::Stdio::piped());
+            }
+            let mut child =
+                command.spawn().chain_err(|| "Failed to start command")?;
+            let stdout = BufReader::new(child.stdout.take().unwrap());
+            match self.protocol {
+                Protocol::Plain => {
+                    self.read_plain(stdout, &window_command_channel)?
+                }
+                Protocol::I3bar => self.read_i3bar(
+                    stdout,
+                    child.stdin.take().unwrap(),
+                    &window_command_channel,
+                )?,
+            }
+        }
+    }
+}
+
+impl PushedCommand {
+    fn read_plain<R: BufRead>(
+        &self,
+        stdout: R,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        for line in stdout.lines() {
+            let line = line.chain_err(|| "Failed to read line")?;
+            trace!("Got output from pushed command: {}", line);
+            self.set_output(line.trim(), window_command_channel);
+        }
+        Ok(())
+    }
+
+    fn read_i3bar<R: BufRead>(
+        &self,
+        stdout: R,
+        stdin: process::ChildStdin,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let mut lines = stdout.lines();
+        let header = match lines.next() {
+            Some(header) => header.chain_err(|| "Failed to read line")?,
+            None => return Ok(()),
+        };
+        let header = i3bar::parse_header(&header)?;
+        *self.click_writer.lock().unwrap() = if header.click_events {
+            Some(ClickWriter::new(stdin)?)
+        } else {
+            None
+        };
+
+        for line in lines {
+            let line = line.chain_err(|| "Failed to read line")?;
+            trace!("Got i3bar status line: {}", line);
+            let blocks = match i3bar::parse_status_line(&line) {
+                Ok(Some(blocks)) => blocks,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!("Skipping status line: {}", err);
+                    continue;
                 }
+            };
+            let urgent = blocks.iter().any(|block| block.urgent);
+            *self.blocks.lock().unwrap() = blocks;
+            if urgent || self.trigger_show {
+                window_command_channel.send(window::Command::Show).unwrap();
             }
         }
+        Ok(())
     }
 }
 
-impl Item for PushedCommand {}
+impl Item for PushedCommand {
+    fn click(&self, click: &window::Click) -> Result<()> {
+        let blocks = self.blocks.lock().unwrap();
+        let mut click_writer = self.click_writer.lock().unwrap();
+        match (blocks.get(click.cell), click_writer.as_mut()) {
+            (Some(block), Some(click_writer)) => {
+                click_writer.write(block, click)
+            }
+            _ => Ok(()),
+        }
+    }
+}
 
 impl ItemFromConfig for PushedCommand {
     fn name() -> &'static str { "pushed-command" }
 
     fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
-        Ok(Box::new(PushedCommand::parse(config)?))
+        config_get!(protocol, config, into_string, "plain".into());
+        let mut command = PushedCommand::parse(config)?;
+        command.protocol = protocol.parse()?;
+        if command.protocol == Protocol::I3bar
+            && command.output_format != OutputFormat::Markup
+        {
+            bail!(ErrorKind::ConfigError(
+                "output-format can't be set for i3bar commands".into()
+            ));
+        }
+        Ok(Box::new(command))
     }
 }
@@ -0,0 +1,272 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, Markup, PulledItem, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+/// CPU usage, sampled from `/proc/stat`
+pub struct Cpu {
+    proc_path: PathBuf,
+    format: String,
+    interval: Duration,
+    trigger_show: bool,
+    /// Times from the last pull, which usage is calculated relative to
+    last_times: Mutex<HashMap<String, CpuTimes>>,
+    text: Mutex<Markup>,
+    text_config: TextConfig,
+}
+
+/// Time spent by a CPU since boot, in clock ticks
+#[derive(Clone, Copy, Default)]
+struct CpuTimes {
+    idle: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    /// Get the percentage of time spent busy since `last`
+    fn usage_since(&self, last: &CpuTimes) -> f64 {
+        let total = self.total.saturating_sub(last.total);
+        let idle = self.idle.saturating_sub(last.idle);
+        if total == 0 {
+            return 0.0;
+        }
+        (total - idle.min(total)) as f64 / total as f64 * 100.0
+    }
+}
+
+impl Cpu {
+    /// Read the times of the total `cpu` and each core `cpuN`
+    fn read_times(&self) -> Result<HashMap<String, CpuTimes>> {
+        let stat_path = self.proc_path.join("stat");
+        let stat = fs::read_to_string(&stat_path)
+            .chain_err(|| format!("Failed to read {}", stat_path.display()))?;
+        stat.lines()
+            .filter(|line| line.starts_with("cpu"))
+            .map(parse_cpu_line)
+            .collect()
+    }
+}
+
+impl PulledItem for Cpu {
+    fn pull(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let times = match self.read_times() {
+            Ok(times) => times,
+            Err(e) => {
+                warn!("Failed to read CPU usage: {}", e);
+                *self.text.lock().unwrap() = Markup::error("No CPU usage");
+                return Ok(());
+            }
+        };
+
+        // On the first pull, this is the usage since boot
+        let mut last_times = self.last_times.lock().unwrap();
+        let usage = |name: &str| -> f64 {
+            let last = last_times.get(name).cloned().unwrap_or_default();
+            times.get(name).map_or(0.0, |t| t.usage_since(&last))
+        };
+        let mut core_names = times
+            .keys()
+            .filter(|name| *name != "cpu")
+            .collect::<Vec<_>>();
+        core_names.sort_by_key(|name| name[3..].parse::<u32>().ok());
+
+        let mut values = HashMap::new();
+        let total = usage("cpu");
+        values.insert("usage".to_string(), format!("{:.0}", total));
+        let mut cores = vec![];
+        for name in core_names {
+            let core_usage = format!("{:.0}", usage(name));
+            values.insert(format!("core{}", &name[3..]), core_usage.clone());
+            cores.push(core_usage);
+        }
+        values.insert("cores".into(), cores.join(" "));
+        trace!("Updating CPU usage: {:.0}%", total);
+        *last_times = times;
+
+        *self.text.lock().unwrap() =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+        if self.trigger_show {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+        Ok(())
+    }
+
+    fn get_interval(&self) -> Duration { self.interval }
+}
+
+impl TextItem for Cpu {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok((self.text.lock().unwrap().clone(), self.text_config.clone()))
+    }
+}
+
+impl Item for Cpu {}
+
+impl ItemFromConfig for Cpu {
+    fn name() -> &'static str { "cpu" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(proc_path, config, into_string, "/proc".into());
+        config_get!(format, config, into_string, "{usage}%".into());
+        config_get!(interval_sec, config, as_f64, 2.0);
+        config_get!(trigger_show, config, as_bool, false);
+
+        // Per-core keys depend on the number of cores, so can't be listed
+        for key in util::template_keys(&format) {
+            let is_core = key.starts_with("core")
+                && key[4..].parse::<u32>().is_ok();
+            ensure!(
+                is_core || key == "usage" || key == "cores",
+                ErrorKind::ConfigError(format!(
+                    "Unrecognized key {{{}}} in format, expected one of: \
+                     usage, cores, core0, core1, ...",
+                    key
+                ))
+            );
+        }
+
+        Ok(Box::new(Cpu {
+            proc_path: proc_path.into(),
+            format,
+            interval: util::duration_from_config(
+                "interval-sec",
+                interval_sec,
+            )?,
+            trigger_show,
+            last_times: Mutex::new(HashMap::new()),
+            text: Mutex::new(Markup::default()),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Parse a line such as `cpu0 4705 356 584 3699 23 23 0 0 0 0`
+fn parse_cpu_line(line: &str) -> Result<(String, CpuTimes)> {
+    let mut fields = line.split_whitespace();
+    let name = fields.next().unwrap_or_default().to_string();
+    let times = fields
+        .map(|field| field.parse::<u64>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .chain_err(|| format!("Failed to parse CPU times: {}", line))?;
+    ensure!(
+        times.len() >= 4,
+        ErrorKind::CommandError(format!("Too few CPU times: {}", line))
+    );
+    // Fields are user, nice, system, idle, iowait, irq, softirq, steal, then
+    // guest times which are already counted in user and nice
+    let idle = times[3] + times.get(4).unwrap_or(&0);
+    let total = times.iter().take(8).sum();
+    Ok((name, CpuTimes { idle, total }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::{Content, Style};
+
+    use sfml::graphics::Color;
+    use tempfile::TempDir;
+
+    const BASE_STYLE: Style = Style {
+        color: Color::WHITE,
+        bold: false,
+        italic: false,
+        size: 12,
+    };
+
+    fn cpu(proc_path: &TempDir) -> Cpu {
+        Cpu {
+            proc_path: proc_path.path().into(),
+            format: "{usage} [{cores}] {core1}".into(),
+            interval: Duration::from_secs(2),
+            trigger_show: false,
+            last_times: Mutex::new(HashMap::new()),
+            text: Mutex::new(Markup::default()),
+            text_config: TextConfig::parse(&mut Config::new()).unwrap(),
+        }
+    }
+
+    /// Write `/proc/stat` and pull the CPU usage
+    fn pull(cpu: &Cpu, proc_path: &TempDir, stat: &str) -> String {
+        fs::write(proc_path.path().join("stat"), stat).unwrap();
+        let (window_command_channel, _window_commands) = mpsc::channel();
+        cpu.pull(window_command_channel).unwrap();
+        cpu.text
+            .lock()
+            .unwrap()
+            .runs(BASE_STYLE)
+            .into_iter()
+            .filter_map(|run| match run.content {
+                Content::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_cpu_line_ignores_guest_times() {
+        let (name, times) =
+            parse_cpu_line("cpu0 10 20 30 40 50 60 70 80 90 100").unwrap();
+        assert_eq!(name, "cpu0");
+        assert_eq!(times.idle, 90);
+        assert_eq!(times.total, 360);
+    }
+
+    #[test]
+    fn parse_cpu_line_rejects_invalid_lines() {
+        assert!(parse_cpu_line("cpu0 10 20 30").is_err());
+        assert!(parse_cpu_line("cpu0 10 20 thirty 40").is_err());
+    }
+
+    #[test]
+    fn pull_calculates_usage_between_snapshots() {
+        let proc_path = TempDir::new().unwrap();
+        let cpu = cpu(&proc_path);
+        let first = pull(
+            &cpu,
+            &proc_path,
+            "cpu  100 0 100 800 0 0 0 0 0 0\n\
+             cpu0 50 0 50 400 0 0 0 0 0 0\n\
+             cpu1 50 0 50 400 0 0 0 0 0 0\n\
+             intr 12345\n",
+        );
+        // The first pull is the usage since boot
+        assert_eq!(first, "20 [20 20] 20");
+
+        let second = pull(
+            &cpu,
+            &proc_path,
+            "cpu  250 0 250 1000 0 0 0 0 0 0\n\
+             cpu0 150 0 150 500 0 0 0 0 0 0\n\
+             cpu1 100 0 100 500 0 0 0 0 0 0\n\
+             intr 12345\n",
+        );
+        assert_eq!(second, "60 [67 50] 50");
+    }
+
+    #[test]
+    fn pull_sorts_cores_numerically() {
+        let proc_path = TempDir::new().unwrap();
+        let cpu = cpu(&proc_path);
+        let mut stat = "cpu  0 0 0 0\n".to_string();
+        for core in 0..11 {
+            // Each core is busy for its number of ticks out of ten
+            stat += &format!("cpu{} {} 0 0 {}\n", core, core, 10 - core);
+        }
+        let text = pull(&cpu, &proc_path, &stat);
+        assert_eq!(text, "0 [0 10 20 30 40 50 60 70 80 90 100] 10");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::text_item;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+    RETRY_INTERVAL,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use chrono::Local;
+use glob::Pattern;
+use inotify::{EventMask, Inotify, WatchMask};
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["name", "path", "time"];
+
+/// Files recently created in or moved into a directory, newest first. Each
+/// file is drawn in its own grid cell
+pub struct Directory {
+    path: PathBuf,
+    /// Only show files with names matching one of these, or all files if
+    /// empty
+    include: Vec<Pattern>,
+    /// Don't show files with names matching any of these
+    exclude: Vec<Pattern>,
+    max_entries: usize,
+    format: String,
+    trigger_show: bool,
+    /// Names of the files shown, and their texts
+    entries: Mutex<VecDeque<(String, Markup)>>,
+    text_config: TextConfig,
+}
+
+impl Directory {
+    fn is_included(&self, name: &str) -> bool {
+        (self.include.is_empty()
+            || self.include.iter().any(|pattern| pattern.matches(name)))
+            && !self.exclude.iter().any(|pattern| pattern.matches(name))
+    }
+
+    fn add_entry(&self, name: &str) {
+        let mut values = HashMap::new();
+        values.insert("name", name.to_string());
+        values.insert("path", self.path.join(name).display().to_string());
+        values.insert("time", Local::now().format("%H:%M").to_string());
+        let markup =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(entry_name, _)| entry_name != name);
+        entries.push_front((name.to_string(), markup));
+        entries.truncate(self.max_entries);
+    }
+
+    fn remove_entry(&self, name: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(entry_name, _)| entry_name != name);
+    }
+
+    /// Watch the directory until it's removed
+    fn watch(
+        &self,
+        inotify: &mut Inotify,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        inotify
+            .watches()
+            .add(
+                &self.path,
+                WatchMask::CREATE
+                    | WatchMask::MOVED_TO
+                    | WatchMask::DELETE
+                    | WatchMask::MOVED_FROM,
+            )
+            .chain_err(|| format!("Failed to watch {}", self.path.display()))?;
+
+        let mut buffer = [0; 4096];
+        loop {
+            let events = inotify
+                .read_events_blocking(&mut buffer)
+                .chain_err(|| "Failed to read inotify events")?;
+            let mut added = false;
+            for event in events {
+                if event.mask.contains(EventMask::IGNORED) {
+                    debug!("Directory {} was removed", self.path.display());
+                    self.entries.lock().unwrap().clear();
+                    return Ok(());
+                }
+                let name = match event.name {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => continue,
+                };
+                if !self.is_included(&name) {
+                    continue;
+                }
+                let appeared = EventMask::CREATE | EventMask::MOVED_TO;
+                if event.mask.intersects(appeared) {
+                    debug!("New file in {}: {}", self.path.display(), name);
+                    self.add_entry(&name);
+                    added = true;
+                } else {
+                    self.remove_entry(&name);
+                }
+            }
+            if added && self.trigger_show {
+                window_command_channel.send(window::Command::Show).unwrap();
+            }
+        }
+    }
+}
+
+impl ItemStart for Directory {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let mut inotify =
+            Inotify::init().chain_err(|| "Failed to initialise inotify")?;
+        loop {
+            if let Err(e) = self.watch(&mut inotify, &window_command_channel) {
+                warn!("Failed to watch directory: {}", e);
+            }
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+}
+
+impl TextItem for Directory {
+    /// Get the text of the newest file
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok(text_item::first_text(self.get_texts()?, &self.text_config))
+    }
+
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, markup)| (markup.clone(), self.text_config.clone()))
+            .collect())
+    }
+}
+
+impl Item for Directory {}
+
+impl ItemFromConfig for Directory {
+    fn name() -> &'static str { "directory" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(path, config, into_string, required);
+        config_get!(include, config, into_string, list);
+        config_get!(exclude, config, into_string, list);
+        config_get!(max_entries, config, as_i64, 5);
+        config_get!(format, config, into_string, "{name}".into());
+        config_get!(trigger_show, config, as_bool, true);
+        util::check_template(&format, FORMAT_KEYS)?;
+        ensure!(
+            max_entries >= 1,
+            ErrorKind::ConfigError("'max-entries' must be at least 1".into())
+        );
+
+        Ok(Box::new(Directory {
+            path: path.into(),
+            include: parse_patterns(&include)?,
+            exclude: parse_patterns(&exclude)?,
+            max_entries: max_entries as usize,
+            format,
+            trigger_show,
+            entries: Mutex::new(VecDeque::new()),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern)
+                .chain_err(|| format!("Invalid file pattern: {}", pattern))
+        })
+        .collect()
+}
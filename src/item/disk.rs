@@ -0,0 +1,211 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::text_item;
+use crate::item::{
+    Item, ItemFromConfig, Markup, PulledItem, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use nix::sys::statvfs::statvfs;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &[
+    "mount",
+    "used",
+    "free",
+    "total",
+    "used_percent",
+    "free_percent",
+];
+
+/// Disk usage of mount points. Each mount point is drawn in its own grid cell
+pub struct Disk {
+    mount_points: Vec<String>,
+    format: String,
+    interval: Duration,
+    trigger_show: bool,
+    /// Show the panel when any mount point fills past this percentage
+    full_percent: Option<f64>,
+    /// Mount points that were full on the last pull
+    last_full: Mutex<Vec<String>>,
+    texts: Mutex<Vec<Markup>>,
+    text_config: TextConfig,
+}
+
+/// Space on a file system, in bytes
+struct Usage {
+    used: u64,
+    /// Space available to unprivileged users
+    free: u64,
+    total: u64,
+}
+
+impl Usage {
+    /// Percentage of the space usable by unprivileged users that is used, as
+    /// reported by `df`
+    fn used_percent(&self) -> f64 {
+        let usable = self.used + self.free;
+        if usable == 0 {
+            return 0.0;
+        }
+        self.used as f64 / usable as f64 * 100.0
+    }
+}
+
+impl Disk {
+    fn format_usage(&self, mount_point: &str, usage: &Usage) -> Markup {
+        let used_percent = usage.used_percent();
+        let mut values = HashMap::new();
+        values.insert("mount", mount_point.to_string());
+        values.insert("used", util::human_bytes(usage.used as f64));
+        values.insert("free", util::human_bytes(usage.free as f64));
+        values.insert("total", util::human_bytes(usage.total as f64));
+        values.insert("used_percent", format!("{:.0}", used_percent));
+        values.insert("free_percent", format!("{:.0}", 100.0 - used_percent));
+        let markup =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+
+        if self.is_full(usage) {
+            markup.urgent()
+        } else {
+            markup
+        }
+    }
+
+    fn is_full(&self, usage: &Usage) -> bool {
+        matches!(self.full_percent, Some(full) if usage.used_percent() >= full)
+    }
+}
+
+impl PulledItem for Disk {
+    fn pull(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let mounted = mounted_points();
+        let mut texts = vec![];
+        let mut full = vec![];
+        for mount_point in &self.mount_points {
+            match read_usage(mount_point, mounted.as_deref()) {
+                Ok(usage) => {
+                    if self.is_full(&usage) {
+                        full.push(mount_point.clone());
+                    }
+                    texts.push(self.format_usage(mount_point, &usage));
+                }
+                Err(e) => {
+                    warn!("Failed to read disk usage: {}", e);
+                    texts.push(Markup::error(&format!(
+                        "{}: unavailable",
+                        mount_point
+                    )));
+                }
+            }
+        }
+        trace!("Updating disk: {} full mount points", full.len());
+        *self.texts.lock().unwrap() = texts;
+
+        // Mount points that were full on the last pull were already announced
+        let mut last_full = self.last_full.lock().unwrap();
+        let became_full = full
+            .iter()
+            .any(|mount_point| !last_full.contains(mount_point));
+        *last_full = full;
+        if self.trigger_show || became_full {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+        Ok(())
+    }
+
+    fn get_interval(&self) -> Duration { self.interval }
+}
+
+impl TextItem for Disk {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok(text_item::first_text(self.get_texts()?, &self.text_config))
+    }
+
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        Ok(text_item::cell_texts(&self.texts, &self.text_config))
+    }
+}
+
+impl Item for Disk {}
+
+impl ItemFromConfig for Disk {
+    fn name() -> &'static str { "disk" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(mount_points, config, into_string, list);
+        config_get!(
+            format,
+            config,
+            into_string,
+            "{mount}: {used_percent}%".into()
+        );
+        config_get!(interval_sec, config, as_f64, 60.0);
+        config_get!(trigger_show, config, as_bool, false);
+        config_get!(full_percent, config, as_i64);
+        util::check_template(&format, FORMAT_KEYS)?;
+        ensure!(
+            !mount_points.is_empty(),
+            ErrorKind::ConfigError("Disk item has no mount points".into())
+        );
+
+        Ok(Box::new(Disk {
+            mount_points,
+            format,
+            interval: util::duration_from_config(
+                "interval-sec",
+                interval_sec,
+            )?,
+            trigger_show,
+            full_percent: full_percent.map(|percent| percent as f64),
+            last_full: Mutex::new(vec![]),
+            texts: Mutex::new(vec![]),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Get the usage of a mount point. If `mounted` is given, the mount point
+/// must be in it, so that the usage of the file system containing an
+/// unmounted directory isn't shown
+fn read_usage(mount_point: &str, mounted: Option<&[String]>) -> Result<Usage> {
+    if let Some(mounted) = mounted {
+        ensure!(
+            mounted.iter().any(|m| m == mount_point),
+            ErrorKind::CommandError(format!("{} is not mounted", mount_point))
+        );
+    }
+    let stat = statvfs(mount_point)
+        .chain_err(|| format!("Failed to get usage of {}", mount_point))?;
+    let fragment_size = stat.fragment_size() as u64;
+    let total = stat.blocks() as u64 * fragment_size;
+    let used = total - stat.blocks_free() as u64 * fragment_size;
+    Ok(Usage {
+        used,
+        free: stat.blocks_available() as u64 * fragment_size,
+        total,
+    })
+}
+
+/// Get the mount points in `/proc/mounts`, or `None` if it can't be read
+fn mounted_points() -> Option<Vec<String>> {
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+    Some(
+        mounts
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(1))
+            // Spaces in paths are escaped
+            .map(|mount_point| mount_point.replace("\\040", " "))
+            .collect(),
+    )
+}
@@ -0,0 +1,187 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+    RETRY_INTERVAL,
+};
+use crate::window;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use inotify::{EventMask, Inotify, WatchMask};
+
+/// Contents of a file, updated when inotify reports that the file changed
+pub struct File {
+    path: PathBuf,
+    /// Only show this many lines from the end of the file
+    lines: Option<usize>,
+    trigger_show: bool,
+    /// Contents shown, or `None` if the file couldn't be read, which is the
+    /// case until the file is first read
+    contents: Mutex<Option<String>>,
+    text: Mutex<Markup>,
+    text_config: TextConfig,
+}
+
+impl File {
+    /// Read the file and update the text. Returns whether the contents
+    /// changed since the last update
+    fn update(&self) -> bool {
+        let contents = match fs::read(&self.path) {
+            Ok(bytes) => {
+                let contents = String::from_utf8_lossy(&bytes);
+                let contents = contents.trim_end();
+                Some(match self.lines {
+                    Some(lines) => {
+                        let all_lines = contents.lines().collect::<Vec<_>>();
+                        all_lines[all_lines.len().saturating_sub(lines)..]
+                            .join("\n")
+                    }
+                    None => contents.to_string(),
+                })
+            }
+            Err(e) => {
+                debug!("Failed to read {}: {}", self.path.display(), e);
+                None
+            }
+        };
+
+        let mut last_contents = self.contents.lock().unwrap();
+        if *last_contents == contents {
+            return false;
+        }
+        trace!("Updating file {}", self.path.display());
+        *self.text.lock().unwrap() = match &contents {
+            Some(contents) => Markup::parse_or_plain(contents),
+            None => not_found(&self.path),
+        };
+        *last_contents = contents;
+        true
+    }
+
+    /// Watch the file's directory, so that the file being deleted and
+    /// recreated is noticed. Returns when the directory is removed
+    fn watch(
+        &self,
+        inotify: &mut Inotify,
+        directory: &Path,
+        file_name: &OsStr,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        inotify
+            .watches()
+            .add(
+                directory,
+                WatchMask::MODIFY
+                    | WatchMask::CLOSE_WRITE
+                    | WatchMask::CREATE
+                    | WatchMask::DELETE
+                    | WatchMask::MOVED_FROM
+                    | WatchMask::MOVED_TO,
+            )
+            .chain_err(|| format!("Failed to watch {}", directory.display()))?;
+        self.update();
+
+        let mut buffer = [0; 4096];
+        loop {
+            let events = inotify
+                .read_events_blocking(&mut buffer)
+                .chain_err(|| "Failed to read inotify events")?
+                .collect::<Vec<_>>();
+            if events
+                .iter()
+                .any(|event| event.mask.contains(EventMask::IGNORED))
+            {
+                debug!("Directory {} was removed", directory.display());
+                self.update();
+                return Ok(());
+            }
+            let file_changed = events
+                .iter()
+                .any(|event| event.name == Some(file_name));
+            if file_changed && self.update() && self.trigger_show {
+                window_command_channel.send(window::Command::Show).unwrap();
+            }
+        }
+    }
+}
+
+impl ItemStart for File {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let directory = match self.path.parent() {
+            Some(directory) if directory != Path::new("") => directory,
+            _ => Path::new("."),
+        };
+        let file_name = self.path.file_name().unwrap();
+        let mut inotify =
+            Inotify::init().chain_err(|| "Failed to initialise inotify")?;
+        loop {
+            if let Err(e) = self.watch(
+                &mut inotify,
+                directory,
+                file_name,
+                &window_command_channel,
+            ) {
+                warn!("Failed to watch file: {}", e);
+                self.update();
+            }
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+}
+
+impl TextItem for File {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok((self.text.lock().unwrap().clone(), self.text_config.clone()))
+    }
+}
+
+impl Item for File {}
+
+impl ItemFromConfig for File {
+    fn name() -> &'static str { "file" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(path, config, into_string, required);
+        config_get!(lines, config, as_i64);
+        config_get!(trigger_show, config, as_bool, false);
+        if let Some(lines) = lines {
+            ensure!(
+                lines >= 1,
+                ErrorKind::ConfigError("'lines' must be at least 1".into())
+            );
+        }
+
+        let path = PathBuf::from(path);
+        ensure!(
+            path.file_name().is_some(),
+            ErrorKind::ConfigError(format!(
+                "File path has no file name: {}",
+                path.display()
+            ))
+        );
+
+        Ok(Box::new(File {
+            lines: lines.map(|lines| lines as usize),
+            trigger_show,
+            contents: Mutex::new(None),
+            text: Mutex::new(not_found(&path)),
+            path,
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Text shown when the file can't be read
+fn not_found(path: &Path) -> Markup {
+    Markup::error(&format!("{} not found", path.display()))
+}
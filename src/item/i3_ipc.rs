@@ -0,0 +1,132 @@
+//! Speaks the [i3 IPC protocol](https://i3wm.org/docs/ipc.html), which sway
+//! also implements
+
+use crate::error::*;
+
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::process;
+
+use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
+use serde_json::Value;
+
+/// Magic string starting every message
+const MAGIC: &[u8] = b"i3-ipc";
+
+#[allow(missing_docs)]
+pub const RUN_COMMAND: u32 = 0;
+#[allow(missing_docs)]
+pub const GET_WORKSPACES: u32 = 1;
+#[allow(missing_docs)]
+pub const SUBSCRIBE: u32 = 2;
+#[allow(missing_docs)]
+pub const GET_BINDING_STATE: u32 = 12;
+
+/// Events have the highest bit of their type set
+#[allow(missing_docs)]
+pub const WORKSPACE_EVENT: u32 = 0x8000_0000;
+#[allow(missing_docs)]
+pub const MODE_EVENT: u32 = 0x8000_0002;
+
+/// Connection to the window manager
+pub struct Connection {
+    stream: UnixStream,
+}
+
+impl Connection {
+    /// Connect to the socket at `socket_path`, or find the socket if `None`
+    pub fn open(socket_path: Option<&str>) -> Result<Self> {
+        let socket_path = match socket_path {
+            Some(socket_path) => socket_path.to_string(),
+            None => find_socket_path()?,
+        };
+        let stream = UnixStream::connect(&socket_path).chain_err(|| {
+            format!("Failed to connect to IPC socket {}", socket_path)
+        })?;
+        Ok(Connection { stream })
+    }
+
+    /// Send a message, and receive its reply. There must be no subscriptions
+    /// on the connection, as events could be received before the reply
+    pub fn request(
+        &mut self,
+        message_type: u32,
+        payload: &str,
+    ) -> Result<Value>
+    {
+        self.send(message_type, payload)?;
+        let (reply_type, reply) = self.receive()?;
+        ensure!(
+            reply_type == message_type,
+            ErrorKind::CommandError(format!(
+                "Expected IPC reply of type {}, got {}",
+                message_type, reply_type
+            ))
+        );
+        Ok(reply)
+    }
+
+    #[allow(missing_docs)]
+    pub fn send(&mut self, message_type: u32, payload: &str) -> Result<()> {
+        let mut message = MAGIC.to_vec();
+        message
+            .write_u32::<NativeEndian>(payload.len() as u32)
+            .and_then(|_| message.write_u32::<NativeEndian>(message_type))
+            .chain_err(|| "Failed to encode IPC message")?;
+        message.extend_from_slice(payload.as_bytes());
+        self.stream
+            .write_all(&message)
+            .chain_err(|| "Failed to send IPC message")
+    }
+
+    /// Receive a reply or event, returning its type and payload
+    pub fn receive(&mut self) -> Result<(u32, Value)> {
+        let mut magic = [0; 6];
+        self.stream
+            .read_exact(&mut magic)
+            .chain_err(|| "Failed to receive IPC message")?;
+        ensure!(
+            magic == MAGIC,
+            ErrorKind::CommandError("Invalid IPC message".into())
+        );
+        let length = self
+            .stream
+            .read_u32::<NativeEndian>()
+            .chain_err(|| "Failed to receive IPC message")?;
+        let message_type = self
+            .stream
+            .read_u32::<NativeEndian>()
+            .chain_err(|| "Failed to receive IPC message")?;
+        let mut payload = vec![0; length as usize];
+        self.stream
+            .read_exact(&mut payload)
+            .chain_err(|| "Failed to receive IPC message")?;
+        let payload = serde_json::from_slice(&payload)
+            .chain_err(|| "Failed to parse IPC message as JSON")?;
+        Ok((message_type, payload))
+    }
+}
+
+/// Find the socket from the environment, or by asking i3 or sway
+fn find_socket_path() -> Result<String> {
+    for variable in &["SWAYSOCK", "I3SOCK"] {
+        if let Ok(socket_path) = env::var(variable) {
+            return Ok(socket_path);
+        }
+    }
+    for window_manager in &["i3", "sway"] {
+        let output = process::Command::new(window_manager)
+            .arg("--get-socketpath")
+            .output();
+        if let Ok(output) = output {
+            let socket_path = String::from_utf8_lossy(&output.stdout);
+            if output.status.success() && !socket_path.trim().is_empty() {
+                return Ok(socket_path.trim().to_string());
+            }
+        }
+    }
+    bail!(ErrorKind::CommandError(
+        "Failed to find IPC socket, is i3 or sway running?".into()
+    ))
+}
@@ -0,0 +1,188 @@
+//! Parses the [i3bar protocol](https://i3wm.org/docs/i3bar-protocol.html),
+//! spoken by i3status, i3blocks, py3status and others
+
+use crate::error::*;
+use crate::item::{Markup, MinWidth, Tag};
+use crate::util;
+use crate::window::Click;
+
+use std::io::Write;
+use std::process::ChildStdin;
+
+use serde_json::{json, Value};
+
+/// Header sent before any status lines
+pub struct Header {
+    /// Whether the command accepts click events on stdin
+    pub click_events: bool,
+}
+
+/// A block in a status line, drawn in its own grid cell
+#[derive(Clone)]
+pub struct Block {
+    #[allow(missing_docs)]
+    pub markup: Markup,
+    #[allow(missing_docs)]
+    pub min_width: Option<MinWidth>,
+    #[allow(missing_docs)]
+    pub urgent: bool,
+    /// Identifies the block in click events
+    pub name: Option<String>,
+    /// Identifies the block in click events
+    pub instance: Option<String>,
+}
+
+/// Writes click events to a command's stdin
+pub struct ClickWriter {
+    stdin: ChildStdin,
+    /// Whether no events have been written, as events after the first are
+    /// prefixed with a comma
+    first: bool,
+}
+
+impl ClickWriter {
+    /// Start the infinite array of click events
+    pub fn new(mut stdin: ChildStdin) -> Result<Self> {
+        stdin
+            .write_all(b"[\n")
+            .chain_err(|| "Failed to start click events")?;
+        Ok(ClickWriter { stdin, first: true })
+    }
+
+    /// Write a click on a block
+    pub fn write(&mut self, block: &Block, click: &Click) -> Result<()> {
+        let event = json!({
+            "name": block.name,
+            "instance": block.instance,
+            "button": click.button,
+            "x": click.x,
+            "y": click.y,
+            "relative_x": click.relative_x,
+            "relative_y": click.relative_y,
+            "width": click.width,
+            "height": click.height,
+        });
+        trace!("Writing click event: {}", event);
+        let separator = if self.first { "" } else { "," };
+        self.first = false;
+        writeln!(self.stdin, "{}{}", separator, event)
+            .chain_err(|| "Failed to write click event")?;
+        self.stdin.flush().chain_err(|| "Failed to write click event")
+    }
+}
+
+/// Parse the header line
+pub fn parse_header(line: &str) -> Result<Header> {
+    let header: Value = serde_json::from_str(line)
+        .chain_err(|| "Failed to parse i3bar header as JSON")?;
+    ensure!(
+        header.get("version").and_then(Value::as_i64).is_some(),
+        ErrorKind::CommandError("i3bar header has no version".into())
+    );
+    Ok(Header {
+        click_events: header
+            .get("click_events")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    })
+}
+
+/// Parse a line of the infinite array of status lines. Returns `None` for
+/// lines that don't contain a status line, such as the opening `[`
+pub fn parse_status_line(line: &str) -> Result<Option<Vec<Block>>> {
+    // Status lines are separated by commas, which can be at the start or end
+    // of the line
+    let line = line.trim().trim_start_matches(',').trim_end_matches(',');
+    if line.is_empty() || line == "[" {
+        return Ok(None);
+    }
+    // The first status line can be on the same line as the opening `[`
+    let line = if line.starts_with("[[") { &line[1..] } else { line };
+
+    let blocks: Vec<Value> = serde_json::from_str(line)
+        .chain_err(|| "Failed to parse i3bar status line as JSON")?;
+    blocks
+        .iter()
+        .map(parse_block)
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+fn parse_block(block: &Value) -> Result<Block> {
+    let get_str = |key: &str| block.get(key).and_then(Value::as_str);
+    let full_text = get_str("full_text").ok_or_else(|| {
+        ErrorKind::CommandError("i3bar block has no full_text".into())
+    })?;
+    let urgent = block
+        .get("urgent")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let mut markup = Markup::plain(full_text);
+    if let Some(color) = get_str("color") {
+        markup = markup
+            .wrap(Tag::Color(util::hex_color(color.trim_start_matches('#'))?));
+    }
+    if urgent {
+        markup = markup.urgent();
+    }
+
+    let min_width = match block.get("min_width") {
+        Some(Value::Number(pixels)) => pixels
+            .as_u64()
+            .map(|pixels| MinWidth::Pixels(pixels as u32)),
+        Some(Value::String(text)) => Some(MinWidth::Text(text.clone())),
+        _ => None,
+    };
+
+    Ok(Block {
+        markup,
+        min_width,
+        urgent,
+        name: get_str("name").map(str::to_string),
+        instance: get_str("instance").map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status_line_skips_array_start() {
+        assert!(parse_status_line("[").unwrap().is_none());
+        assert!(parse_status_line("").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_status_line_handles_framing() {
+        for line in &[
+            r#"[[{"full_text": "a"}, {"full_text": "b"}]"#,
+            r#",[{"full_text": "a"}, {"full_text": "b"}]"#,
+            r#"[{"full_text": "a"}, {"full_text": "b"}],"#,
+        ] {
+            let blocks = parse_status_line(line).unwrap().unwrap();
+            assert_eq!(blocks.len(), 2, "Failed to parse {}", line);
+        }
+    }
+
+    #[test]
+    fn parse_status_line_reads_block_fields() {
+        let line = r#"[{"full_text": "a", "urgent": true, "min_width": 50},
+                       {"full_text": "b", "min_width": "wide"}]"#;
+        let blocks = parse_status_line(line).unwrap().unwrap();
+        assert!(blocks[0].urgent);
+        assert!(matches!(blocks[0].min_width, Some(MinWidth::Pixels(50))));
+        assert!(!blocks[1].urgent);
+        assert!(matches!(
+            &blocks[1].min_width,
+            Some(MinWidth::Text(text)) if text == "wide"
+        ));
+    }
+
+    #[test]
+    fn parse_status_line_rejects_invalid_blocks() {
+        assert!(parse_status_line(r#"[{"text": "a"}]"#).is_err());
+        assert!(parse_status_line(r#"[{"full_text": "a""#).is_err());
+    }
+}
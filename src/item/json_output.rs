@@ -0,0 +1,49 @@
+//! Parses JSON output from commands, which lets commands set the style of
+//! their output and whether to show the panel on every update
+
+use crate::error::*;
+use crate::item::{Markup, Tag};
+use crate::util;
+
+use serde_json::Value;
+
+/// An update from a command
+pub struct Update {
+    #[allow(missing_docs)]
+    pub markup: Markup,
+    /// Whether to show the panel, if the command specified it
+    pub show: Option<bool>,
+    #[allow(missing_docs)]
+    pub urgent: bool,
+}
+
+/// Parse an update, e.g.
+/// `{"text": "...", "color": "ff0000", "urgent": true, "show": true}`
+pub fn parse_update(output: &str) -> Result<Update> {
+    let update: Value = serde_json::from_str(output)
+        .chain_err(|| "Failed to parse command output as JSON")?;
+    ensure!(
+        update.is_object(),
+        ErrorKind::CommandError("JSON output must be an object".into())
+    );
+
+    let text = update.get("text").and_then(Value::as_str).unwrap_or("");
+    let mut markup = Markup::parse_or_plain(text);
+    if let Some(color) = update.get("color").and_then(Value::as_str) {
+        markup = markup
+            .wrap(Tag::Color(util::hex_color(color.trim_start_matches('#'))?));
+    }
+    let urgent = update
+        .get("urgent")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if urgent {
+        markup = markup.urgent();
+    }
+
+    Ok(Update {
+        markup,
+        show: update.get("show").and_then(Value::as_bool),
+        urgent,
+    })
+}
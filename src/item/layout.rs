@@ -0,0 +1,465 @@
+//! Lays out runs of markup into lines
+
+use crate::item::{Bar, Content, Run, Style};
+use crate::window::DrawableConfig;
+
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use sfml::graphics::{
+    Color, Drawable, Font, RectangleShape, RenderStates, RenderTarget, Shape,
+    Sprite, Text, Texture, Transformable,
+};
+use sfml::system::Vector2f;
+
+/// Height of a line relative to the font size
+const FONT_SIZE_SCALE: f32 = 1.40;
+const PLACEHOLDER_COLOR: (u8, u8, u8) = (255, 0, 255);
+const PLACEHOLDER_THICKNESS: f32 = 1.0;
+const ELLIPSIS: &str = "\u{2026}";
+
+/// Resources needed to create drawables for runs
+pub struct Resources<'a> {
+    #[allow(missing_docs)]
+    pub font: &'a Font,
+    #[allow(missing_docs)]
+    pub icon_font: Option<&'a Font>,
+    /// Icon names to characters in the icon font
+    pub icons: &'a HashMap<String, char>,
+    /// Image paths to textures, or `None` if the image failed to load
+    pub textures: &'a HashMap<String, Option<Rc<Texture>>>,
+}
+
+/// Drawables laid out in lines
+pub struct Layout<'a> {
+    lines: Vec<Line<'a>>,
+    /// Wrap lines that are wider than this
+    max_width: Option<f32>,
+}
+
+/// Element in a line
+struct Element<'a> {
+    drawable: Box<dyn Drawable + 'a>,
+    x: f32,
+    width: f32,
+    /// Font size of the element, used to align on the line's baseline
+    size: u32,
+    /// The text, font and style, if the element is text
+    text: Option<(String, &'a Font, Style)>,
+}
+
+#[derive(Default)]
+struct Line<'a> {
+    elements: Vec<Element<'a>>,
+    width: f32,
+    max_size: u32,
+    /// Whether the line was started by wrapping the previous line
+    wrapped: bool,
+}
+
+impl<'a> Layout<'a> {
+    /// Lay out the runs, wrapping lines that are wider than `max_width`
+    pub fn new(
+        runs: &[Run],
+        resources: &Resources<'a>,
+        max_width: Option<f32>,
+    ) -> Self
+    {
+        let mut layout = Layout {
+            lines: vec![Line::default()],
+            max_width,
+        };
+        for run in runs {
+            match &run.content {
+                Content::Text(text) => {
+                    for (i, line) in text.split('\n').enumerate() {
+                        if i > 0 {
+                            layout.break_line(run.style.size);
+                        }
+                        // Only split into words if we might need to wrap
+                        let words = if max_width.is_some() {
+                            split_words(line)
+                        } else {
+                            vec![line]
+                        };
+                        for word in words {
+                            layout.add_text(word, resources.font, &run.style);
+                        }
+                    }
+                }
+                Content::Icon(name) => {
+                    match (resources.icons.get(name), resources.icon_font) {
+                        (Some(icon), Some(icon_font)) => layout.add_text(
+                            &icon.to_string(),
+                            icon_font,
+                            &run.style,
+                        ),
+                        _ => {
+                            trace!("Can't draw icon {}", name);
+                            layout.add_placeholder(&run.style);
+                        }
+                    }
+                }
+                Content::Image(src) => {
+                    let texture =
+                        resources.textures.get(src).and_then(Option::as_ref);
+                    match texture {
+                        Some(texture) => layout.add_image(texture, &run.style),
+                        None => layout.add_placeholder(&run.style),
+                    }
+                }
+                Content::Bar(bar) => {
+                    layout.add_bar(bar, resources.font, &run.style)
+                }
+            }
+        }
+        layout
+    }
+
+    /// Width of the widest line
+    pub fn width(&self) -> f32 {
+        self.lines.iter().map(|l| l.width).fold(0.0, f32::max)
+    }
+
+    /// Total height of all lines
+    pub fn height(&self) -> f32 {
+        self.lines
+            .iter()
+            .map(|l| l.max_size as f32 * FONT_SIZE_SCALE)
+            .sum()
+    }
+
+    /// Truncate lines that are wider than `max_width`, ending them with an
+    /// ellipsis
+    pub fn ellipsize(&mut self, max_width: f32) {
+        for line in &mut self.lines {
+            if line.width <= max_width {
+                continue;
+            }
+
+            let mut kept = vec![];
+            // The ellipsis is drawn with the font and style of the last text
+            let mut last_text: Option<(&Font, Style)> = None;
+            for element in mem::take(&mut line.elements) {
+                if let Some((_, font, style)) = &element.text {
+                    last_text = Some((font, *style));
+                }
+                let (font, style) = match last_text {
+                    Some(last_text) => last_text,
+                    // Lines starting with images are left clipped
+                    None => {
+                        kept.push(element);
+                        continue;
+                    }
+                };
+                let ellipsis = create_text(ELLIPSIS, font, &style);
+                let ellipsis_width = text_width(&ellipsis, ELLIPSIS);
+                if element.x + element.width + ellipsis_width <= max_width {
+                    kept.push(element);
+                    continue;
+                }
+
+                // Keep as many characters as fit before the ellipsis. The
+                // text is measured once, and the cut is found with a binary
+                // search over the positions of its characters
+                let mut x = element.x;
+                if let Some((text, font, style)) = &element.text {
+                    let available = max_width - ellipsis_width - x;
+                    let full_text = create_text(text, font, style);
+                    let chars: Vec<char> = text.chars().collect();
+                    // `fits` characters always fit and `too_many` never do
+                    let (mut fits, mut too_many) = (0, chars.len() + 1);
+                    while too_many - fits > 1 {
+                        let middle = (fits + too_many) / 2;
+                        if full_text.find_character_pos(middle).x <= available
+                        {
+                            fits = middle;
+                        } else {
+                            too_many = middle;
+                        }
+                    }
+                    if fits > 0 {
+                        let prefix: String = chars[..fits].iter().collect();
+                        let sfml_text = create_text(&prefix, font, style);
+                        let width = text_width(&sfml_text, &prefix);
+                        kept.push(Element {
+                            drawable: Box::new(sfml_text),
+                            x,
+                            width,
+                            size: style.size,
+                            text: Some((prefix, font, *style)),
+                        });
+                        x += width;
+                    }
+                }
+                kept.push(Element {
+                    drawable: Box::new(ellipsis),
+                    x,
+                    width: ellipsis_width,
+                    size: style.size,
+                    text: Some((ELLIPSIS.into(), font, style)),
+                });
+                break;
+            }
+
+            line.width = kept.last().map(|e| e.x + e.width).unwrap_or(0.0);
+            line.elements = kept;
+        }
+    }
+
+    /// Get the drawables, translated to their position in the layout and
+    /// offset horizontally by `x_offset`
+    pub fn drawable_configs(
+        &self,
+        x_offset: f32,
+    ) -> Vec<DrawableConfig<'_, '_, '_, '_>>
+    {
+        let mut drawable_configs = vec![];
+        let mut y = 0.0;
+        for line in &self.lines {
+            for element in &line.elements {
+                let size_difference = (line.max_size - element.size) as f32;
+                // Text is drawn with its baseline `size` pixels below its
+                // position, so offset smaller text to share the same
+                // baseline. Other elements are aligned to the bottom of the
+                // line
+                let y_offset = if element.text.is_some() {
+                    size_difference
+                } else {
+                    size_difference * FONT_SIZE_SCALE
+                };
+                let mut drawable_config =
+                    DrawableConfig::new(element.drawable.as_ref());
+                drawable_config
+                    .render_states
+                    .transform
+                    .translate(element.x + x_offset, y + y_offset);
+                drawable_configs.push(drawable_config);
+            }
+            y += line.max_size as f32 * FONT_SIZE_SCALE;
+        }
+        drawable_configs
+    }
+
+    fn add_text(&mut self, text: &str, font: &'a Font, style: &Style) {
+        // Don't start wrapped lines with whitespace
+        let is_whitespace = text.trim().is_empty();
+        if is_whitespace && self.is_wrapped_line_start() {
+            return;
+        }
+
+        let sfml_text = create_text(text, font, style);
+        let width = text_width(&sfml_text, text);
+
+        self.wrap_if_needed(width);
+        if is_whitespace && self.is_wrapped_line_start() {
+            return;
+        }
+        self.add_element(
+            Box::new(sfml_text),
+            width,
+            style.size,
+            Some((text.into(), font, *style)),
+        );
+    }
+
+    fn add_image(&mut self, texture: &'a Texture, style: &Style) {
+        let height = style.size as f32 * FONT_SIZE_SCALE;
+        let scale = height / texture.size().y as f32;
+        let width = texture.size().x as f32 * scale;
+        let mut sprite = Sprite::with_texture(texture);
+        sprite.set_scale((scale, scale));
+
+        self.wrap_if_needed(width);
+        self.add_element(Box::new(sprite), width, style.size, None);
+    }
+
+    fn add_bar(&mut self, bar: &Bar, font: &'a Font, style: &Style) {
+        // Bars are measured in the width of a digit, so that bars line up
+        // with numbers
+        let digit = font.glyph('0' as u32, style.size, style.bold, 0.0);
+        let width = digit.advance * bar.width as f32;
+        let height = style.size as f32 * FONT_SIZE_SCALE;
+        let bar_height = style.size as f32;
+        let bar_top = (height - bar_height) / 2.0;
+
+        let mut empty =
+            RectangleShape::with_size(Vector2f::new(width, bar_height));
+        empty.set_fill_color(&bar.empty);
+        empty.set_position((0.0, bar_top));
+        let mut fill = RectangleShape::with_size(Vector2f::new(
+            width * bar.fraction(),
+            bar_height,
+        ));
+        fill.set_fill_color(&bar.fill.unwrap_or(style.color));
+        fill.set_position((0.0, bar_top));
+        let label = bar.label.as_ref().map(|label| {
+            let mut sfml_text = create_text(label, font, style);
+            let label_width = text_width(&sfml_text, label);
+            sfml_text.set_position(((width - label_width) / 2.0, 0.0));
+            sfml_text
+        });
+
+        self.wrap_if_needed(width);
+        self.add_element(
+            Box::new(BarDrawable { empty, fill, label }),
+            width,
+            style.size,
+            None,
+        );
+    }
+
+    /// Add a placeholder for an image or icon that couldn't be loaded
+    fn add_placeholder(&mut self, style: &Style) {
+        let size = style.size as f32 * FONT_SIZE_SCALE;
+        let inset = PLACEHOLDER_THICKNESS;
+        let mut shape = RectangleShape::with_size(Vector2f::new(
+            size - inset * 2.0,
+            size - inset * 2.0,
+        ));
+        let (r, g, b) = PLACEHOLDER_COLOR;
+        shape.set_fill_color(&Color::rgba(r, g, b, 64));
+        shape.set_outline_color(&Color::rgb(r, g, b));
+        shape.set_outline_thickness(PLACEHOLDER_THICKNESS);
+        shape.set_position((inset, inset));
+
+        self.wrap_if_needed(size);
+        self.add_element(Box::new(shape), size, style.size, None);
+    }
+
+    fn add_element(
+        &mut self,
+        drawable: Box<dyn Drawable + 'a>,
+        width: f32,
+        size: u32,
+        text: Option<(String, &'a Font, Style)>,
+    )
+    {
+        let line = self.lines.last_mut().unwrap();
+        line.elements.push(Element {
+            drawable,
+            x: line.width,
+            width,
+            size,
+            text,
+        });
+        line.width += width;
+        line.max_size = line.max_size.max(size);
+    }
+
+    /// Start a new line if an element of `width` doesn't fit on the current
+    /// line
+    fn wrap_if_needed(&mut self, width: f32) {
+        let line = self.lines.last().unwrap();
+        if let Some(max_width) = self.max_width {
+            if !line.elements.is_empty() && line.width + width > max_width {
+                self.lines.push(Line {
+                    wrapped: true,
+                    ..Line::default()
+                });
+            }
+        }
+    }
+
+    /// Start a new line due to a line break. `size` is used as the height of
+    /// the current line if it is empty
+    fn break_line(&mut self, size: u32) {
+        let line = self.lines.last_mut().unwrap();
+        if line.elements.is_empty() {
+            line.max_size = line.max_size.max(size);
+        }
+        self.lines.push(Line::default());
+    }
+
+    fn is_wrapped_line_start(&self) -> bool {
+        let line = self.lines.last().unwrap();
+        line.wrapped && line.elements.is_empty()
+    }
+}
+
+/// Drawable for a progress bar
+struct BarDrawable<'a> {
+    empty: RectangleShape<'a>,
+    fill: RectangleShape<'a>,
+    label: Option<Text<'a>>,
+}
+
+impl<'a> Drawable for BarDrawable<'a> {
+    fn draw<'b: 'shader, 'texture, 'shader, 'shader_texture>(
+        &'b self,
+        target: &mut dyn RenderTarget,
+        states: RenderStates<'texture, 'shader, 'shader_texture>,
+    )
+    {
+        let with_transform = || RenderStates {
+            transform: states.transform,
+            ..RenderStates::default()
+        };
+        target.draw_with_renderstates(&self.empty, with_transform());
+        target.draw_with_renderstates(&self.fill, with_transform());
+        if let Some(label) = &self.label {
+            target.draw_with_renderstates(label, with_transform());
+        }
+    }
+}
+
+fn create_text<'a>(text: &str, font: &'a Font, style: &Style) -> Text<'a> {
+    let mut sfml_text = Text::new(text, font, style.size);
+    sfml_text.set_style(style.text_style());
+    sfml_text.set_fill_color(&style.color);
+    sfml_text.set_outline_color(&Color::rgb(10, 10, 10));
+    sfml_text.set_outline_thickness(1.0);
+    sfml_text
+}
+
+/// Get the width of the text, including trailing whitespace
+fn text_width(sfml_text: &Text, text: &str) -> f32 {
+    let bounds = sfml_text.global_bounds();
+    let end = sfml_text.find_character_pos(text.chars().count());
+    end.x.max(bounds.left + bounds.width)
+}
+
+/// Split text into words and the whitespace between them
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut in_whitespace = None;
+    for (i, c) in text.char_indices() {
+        let is_whitespace = c.is_whitespace();
+        if in_whitespace.is_some() && in_whitespace != Some(is_whitespace) {
+            words.push(&text[start..i]);
+            start = i;
+        }
+        in_whitespace = Some(is_whitespace);
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_words_keeps_whitespace_between_words() {
+        assert_eq!(split_words("hello  world"), vec!["hello", "  ", "world"]);
+    }
+
+    #[test]
+    fn split_words_keeps_leading_and_trailing_whitespace() {
+        assert_eq!(split_words(" a\tb "), vec![" ", "a", "\t", "b", " "]);
+    }
+
+    #[test]
+    fn split_words_handles_empty_text() {
+        assert!(split_words("").is_empty());
+    }
+
+    #[test]
+    fn split_words_splits_multi_byte_characters() {
+        assert_eq!(split_words("héllo wörld"), vec!["héllo", " ", "wörld"]);
+    }
+}
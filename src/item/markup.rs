@@ -0,0 +1,437 @@
+//! Parses the XML markup that items output into a tree of styled spans
+
+use crate::error::*;
+use crate::util;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use sfml::graphics::{Color, TextStyle};
+
+const DEFAULT_BAR_MAX: f32 = 100.0;
+const DEFAULT_BAR_WIDTH: f32 = 10.0;
+const DEFAULT_BAR_EMPTY_COLOR: (u8, u8, u8) = (60, 60, 60);
+const URGENT_COLOR: (u8, u8, u8) = (255, 80, 80);
+
+/// Parsed markup, made up of text and styled elements
+#[derive(Clone, Default)]
+pub struct Markup {
+    nodes: Vec<Node>,
+}
+
+/// A node in the markup tree
+#[derive(Clone)]
+pub enum Node {
+    /// Plain text
+    Text(String),
+    /// A tag applied to its children
+    Element(Tag, Vec<Node>),
+    /// `<image src='path/to/image.png'/>`
+    Image(String),
+    /// `<icon name='battery-half'/>`
+    Icon(String),
+    /// `<bar value='50'/>`
+    Bar(Bar),
+}
+
+/// Tags that can be used in markup
+#[derive(Clone)]
+pub enum Tag {
+    /// `<color hex='00ff00'>`
+    Color(Color),
+    /// `<b>`
+    Bold,
+    /// `<i>`
+    Italic,
+    /// `<size value='24'>`
+    Size(u32),
+}
+
+/// Style of a span of text, resolved from all enclosing tags
+#[derive(Clone, Copy)]
+#[allow(missing_docs)]
+pub struct Style {
+    pub color: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub size: u32,
+}
+
+impl Style {
+    /// Get the SFML text style
+    pub fn text_style(&self) -> TextStyle {
+        let mut text_style = TextStyle::REGULAR;
+        if self.bold {
+            text_style |= TextStyle::BOLD;
+        }
+        if self.italic {
+            text_style |= TextStyle::ITALIC;
+        }
+        text_style
+    }
+}
+
+/// A span of content with a single style
+#[allow(missing_docs)]
+pub struct Run {
+    pub style: Style,
+    pub content: Content,
+}
+
+/// Content of a run
+pub enum Content {
+    /// Text
+    Text(String),
+    /// Path to an image
+    Image(String),
+    /// Name of an icon
+    Icon(String),
+    /// Progress bar
+    Bar(Bar),
+}
+
+/// Horizontal progress bar, e.g.
+/// `<bar value='30' max='60' width='10' fill='00ff00' empty='333333'
+/// label='Disk'/>`
+#[derive(Clone)]
+pub struct Bar {
+    #[allow(missing_docs)]
+    pub value: f32,
+    /// Value of a full bar
+    pub max: f32,
+    /// Width of the bar in characters
+    pub width: u32,
+    /// Colour of the filled part of the bar, defaulting to the text colour
+    pub fill: Option<Color>,
+    /// Colour of the empty part of the bar
+    pub empty: Color,
+    /// Text drawn over the bar
+    pub label: Option<String>,
+}
+
+impl Bar {
+    /// Get how full the bar is, between zero and one
+    pub fn fraction(&self) -> f32 {
+        if self.max <= 0.0 {
+            return 0.0;
+        }
+        (self.value / self.max).clamp(0.0, 1.0)
+    }
+}
+
+impl Markup {
+    /// Create markup containing only plain text
+    pub fn plain(text: &str) -> Self {
+        Markup {
+            nodes: vec![Node::Text(text.into())],
+        }
+    }
+
+    /// Create markup from a list of nodes
+    pub fn from_nodes(nodes: Vec<Node>) -> Self { Markup { nodes } }
+
+    /// Join markup together, one after another
+    pub fn concat(markups: Vec<Markup>) -> Self {
+        Markup {
+            nodes: markups.into_iter().flat_map(|m| m.nodes).collect(),
+        }
+    }
+
+    /// Wrap the markup in a tag
+    pub fn wrap(self, tag: Tag) -> Self {
+        Markup::from_nodes(vec![Node::Element(tag, self.nodes)])
+    }
+
+    /// Style the markup to show that it is urgent
+    pub fn urgent(self) -> Self {
+        let (r, g, b) = URGENT_COLOR;
+        self.wrap(Tag::Bold).wrap(Tag::Color(Color::rgb(r, g, b)))
+    }
+
+    /// Create markup showing an error
+    pub fn error(message: &str) -> Self { Markup::plain(message).urgent() }
+
+    /// Parse markup from a string
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut reader = Reader::from_str(text);
+        let mut buffer = vec![];
+        // Stack of the currently open elements, with the top level at the
+        // bottom
+        let mut stack: Vec<(Option<Tag>, Vec<Node>)> = vec![(None, vec![])];
+        loop {
+            match reader.read_event(&mut buffer).map_err(markup_error)? {
+                Event::Start(ref start) => {
+                    stack.push((Some(parse_tag(start, &reader)?), vec![]));
+                }
+                Event::End(_) => {
+                    ensure!(
+                        stack.len() > 1,
+                        ErrorKind::MarkupError("Unexpected end tag".into())
+                    );
+                    let (tag, nodes) = stack.pop().unwrap();
+                    let element = Node::Element(tag.unwrap(), nodes);
+                    stack.last_mut().unwrap().1.push(element);
+                }
+                Event::Empty(ref start) => {
+                    let node = parse_empty_tag(start, &reader)?;
+                    stack.last_mut().unwrap().1.push(node);
+                }
+                Event::Text(ref text) | Event::CData(ref text) => {
+                    let text = text
+                        .unescape_and_decode(&reader)
+                        .map_err(markup_error)?;
+                    stack.last_mut().unwrap().1.push(Node::Text(text));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buffer.clear();
+        }
+
+        ensure!(
+            stack.len() == 1,
+            ErrorKind::MarkupError("Unclosed tag".into())
+        );
+        Ok(Markup {
+            nodes: stack.pop().unwrap().1,
+        })
+    }
+
+    /// Parse markup from a string, falling back to plain text if the markup
+    /// is malformed
+    pub fn parse_or_plain(text: &str) -> Self {
+        Markup::parse(text).unwrap_or_else(|err| {
+            warn!("Failed to parse markup, using plain text: {}", err);
+            Markup::plain(text)
+        })
+    }
+
+    /// Flatten the markup into a list of runs, in left-to-right order
+    pub fn runs(&self, base_style: Style) -> Vec<Run> {
+        let mut runs = vec![];
+        add_runs(&self.nodes, base_style, &mut runs);
+        runs
+    }
+}
+
+fn add_runs(nodes: &[Node], style: Style, runs: &mut Vec<Run>) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => runs.push(Run {
+                style,
+                content: Content::Text(text.clone()),
+            }),
+            Node::Image(src) => runs.push(Run {
+                style,
+                content: Content::Image(src.clone()),
+            }),
+            Node::Icon(name) => runs.push(Run {
+                style,
+                content: Content::Icon(name.clone()),
+            }),
+            Node::Bar(bar) => runs.push(Run {
+                style,
+                content: Content::Bar(bar.clone()),
+            }),
+            Node::Element(tag, children) => {
+                let mut style = style;
+                match tag {
+                    Tag::Color(color) => style.color = *color,
+                    Tag::Bold => style.bold = true,
+                    Tag::Italic => style.italic = true,
+                    Tag::Size(size) => style.size = *size,
+                }
+                add_runs(children, style, runs);
+            }
+        }
+    }
+}
+
+fn parse_tag(start: &BytesStart, reader: &Reader<&[u8]>) -> Result<Tag> {
+    Ok(match start.name() {
+        b"color" => {
+            let hex = required_attribute(start, reader, "hex")?;
+            Tag::Color(util::hex_color(&hex)?)
+        }
+        b"b" => Tag::Bold,
+        b"i" => Tag::Italic,
+        b"size" => Tag::Size(
+            required_attribute(start, reader, "value")?
+                .parse()
+                .chain_err(|| "Failed to parse size value")?,
+        ),
+        _ => bail!(ErrorKind::MarkupError(format!(
+            "Unrecognized tag: {}",
+            tag_name(start)
+        ))),
+    })
+}
+
+fn parse_empty_tag(start: &BytesStart, reader: &Reader<&[u8]>) -> Result<Node> {
+    Ok(match start.name() {
+        b"image" => Node::Image(required_attribute(start, reader, "src")?),
+        b"icon" => Node::Icon(required_attribute(start, reader, "name")?),
+        b"bar" => Node::Bar(parse_bar(start, reader)?),
+        _ => bail!(ErrorKind::MarkupError(format!(
+            "Unrecognized empty tag: {}",
+            tag_name(start)
+        ))),
+    })
+}
+
+fn parse_bar(start: &BytesStart, reader: &Reader<&[u8]>) -> Result<Bar> {
+    let number = |key: &str, default: Option<f32>| -> Result<f32> {
+        match attribute(start, reader, key)? {
+            Some(value) => value.parse().chain_err(|| {
+                ErrorKind::MarkupError(format!(
+                    "Invalid bar {}: {}",
+                    key, value
+                ))
+            }),
+            None => default.ok_or_else(|| {
+                ErrorKind::MarkupError(format!("Bar is missing {}", key))
+                    .into()
+            }),
+        }
+    };
+    let color = |key: &str| -> Result<Option<Color>> {
+        match attribute(start, reader, key)? {
+            Some(hex) => Ok(Some(util::hex_color(&hex)?)),
+            None => Ok(None),
+        }
+    };
+    Ok(Bar {
+        value: number("value", None)?,
+        max: number("max", Some(DEFAULT_BAR_MAX))?,
+        width: number("width", Some(DEFAULT_BAR_WIDTH))? as u32,
+        fill: color("fill")?,
+        empty: color("empty")?.unwrap_or_else(|| {
+            let (r, g, b) = DEFAULT_BAR_EMPTY_COLOR;
+            Color::rgb(r, g, b)
+        }),
+        label: attribute(start, reader, "label")?,
+    })
+}
+
+/// Get the value of an attribute on a tag, if it exists
+fn attribute(
+    start: &BytesStart,
+    reader: &Reader<&[u8]>,
+    key: &str,
+) -> Result<Option<String>>
+{
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(markup_error)?;
+        if attribute.key == key.as_bytes() {
+            return Ok(Some(
+                attribute
+                    .unescape_and_decode_value(reader)
+                    .map_err(markup_error)?,
+            ));
+        }
+    }
+    Ok(None)
+}
+
+/// Get the value of an attribute on a tag, failing if it does not exist
+fn required_attribute(
+    start: &BytesStart,
+    reader: &Reader<&[u8]>,
+    key: &str,
+) -> Result<String>
+{
+    attribute(start, reader, key)?.ok_or_else(|| {
+        ErrorKind::MarkupError(format!(
+            "Tag {} is missing attribute {}",
+            tag_name(start),
+            key
+        ))
+        .into()
+    })
+}
+
+fn tag_name(start: &BytesStart) -> String {
+    String::from_utf8_lossy(start.name()).into()
+}
+
+fn markup_error(err: quick_xml::Error) -> Error {
+    ErrorKind::MarkupError(err.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_STYLE: Style = Style {
+        color: Color::WHITE,
+        bold: false,
+        italic: false,
+        size: 12,
+    };
+
+    /// Get the text and style of each run, skipping the empty text that the
+    /// reader finds between adjacent tags
+    fn texts(markup: &Markup) -> Vec<(String, Style)> {
+        markup
+            .runs(BASE_STYLE)
+            .into_iter()
+            .map(|run| match run.content {
+                Content::Text(text) => (text, run.style),
+                _ => panic!("Expected only text runs"),
+            })
+            .filter(|(text, _)| !text.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn parse_nested_tags() {
+        let markup = Markup::parse("<b>bold <i>both</i></b> plain").unwrap();
+        let texts = texts(&markup);
+        assert_eq!(texts.len(), 3);
+        assert_eq!(texts[0].0, "bold ");
+        assert!(texts[0].1.bold && !texts[0].1.italic);
+        assert_eq!(texts[1].0, "both");
+        assert!(texts[1].1.bold && texts[1].1.italic);
+        assert_eq!(texts[2].0, " plain");
+        assert!(!texts[2].1.bold && !texts[2].1.italic);
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let markup =
+            Markup::parse("<color hex='00ff00'><size value='20'>big</size>\
+                           </color>")
+                .unwrap();
+        let texts = texts(&markup);
+        assert_eq!(texts[0].0, "big");
+        assert!(texts[0].1.color == Color::rgb(0, 255, 0));
+        assert_eq!(texts[0].1.size, 20);
+    }
+
+    #[test]
+    fn parse_unescapes_entities() {
+        let markup = Markup::parse("a &lt; b &amp;&amp; c").unwrap();
+        assert_eq!(texts(&markup)[0].0, "a < b && c");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_markup() {
+        for text in &[
+            "<b>unclosed",
+            "unopened</b>",
+            "<blink>unknown</blink>",
+            "<color>no hex</color>",
+            "<size value='big'>bad size</size>",
+        ] {
+            assert!(Markup::parse(text).is_err(), "Parsed {}", text);
+        }
+    }
+
+    #[test]
+    fn parse_or_plain_falls_back_to_plain_text() {
+        let markup = Markup::parse_or_plain("<b>oops");
+        let texts = texts(&markup);
+        assert_eq!(texts.len(), 1);
+        assert_eq!(texts[0].0, "<b>oops");
+        assert!(!texts[0].1.bold);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, Markup, PulledItem, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &[
+    "used",
+    "available",
+    "total",
+    "used_percent",
+    "swap_used",
+    "swap_free",
+    "swap_total",
+    "swap_used_percent",
+];
+
+/// Memory and swap usage, read from `/proc/meminfo`
+pub struct Memory {
+    proc_path: PathBuf,
+    format: String,
+    interval: Duration,
+    trigger_show: bool,
+    /// Show the panel when available memory drops below this many bytes
+    low_available: Option<u64>,
+    last_available: Mutex<Option<u64>>,
+    text: Mutex<Markup>,
+    text_config: TextConfig,
+}
+
+/// Memory usage, in bytes
+struct MemoryInfo {
+    total: u64,
+    available: u64,
+    swap_total: u64,
+    swap_free: u64,
+}
+
+impl Memory {
+    fn read_info(&self) -> Result<MemoryInfo> {
+        let meminfo_path = self.proc_path.join("meminfo");
+        let meminfo = fs::read_to_string(&meminfo_path).chain_err(|| {
+            format!("Failed to read {}", meminfo_path.display())
+        })?;
+
+        // Lines are of the form `MemTotal:       16303412 kB`
+        let fields: HashMap<&str, u64> = meminfo
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let name = parts.next()?.trim_end_matches(':');
+                let value = parts.next()?.parse::<u64>().ok()?;
+                let multiplier = match parts.next() {
+                    Some("kB") => 1024,
+                    _ => 1,
+                };
+                Some((name, value * multiplier))
+            })
+            .collect();
+        let get = |name: &str| {
+            fields.get(name).cloned().chain_err(|| {
+                format!("No {} in {}", name, meminfo_path.display())
+            })
+        };
+
+        let total = get("MemTotal")?;
+        // Older kernels don't report available memory, so estimate it
+        let available = get("MemAvailable").or_else(|_| -> Result<u64> {
+            Ok(get("MemFree")? + get("Buffers")? + get("Cached")?)
+        })?;
+        Ok(MemoryInfo {
+            total,
+            available: available.min(total),
+            swap_total: get("SwapTotal").unwrap_or(0),
+            swap_free: get("SwapFree").unwrap_or(0),
+        })
+    }
+
+    fn format_info(&self, info: &MemoryInfo) -> Markup {
+        let percent = |part: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                part as f64 / total as f64 * 100.0
+            }
+        };
+        let used = info.total - info.available;
+        let swap_used = info.swap_total.saturating_sub(info.swap_free);
+
+        let mut values = HashMap::new();
+        values.insert("used", util::human_bytes(used as f64));
+        values.insert("available", util::human_bytes(info.available as f64));
+        values.insert("total", util::human_bytes(info.total as f64));
+        values.insert(
+            "used_percent",
+            format!("{:.0}", percent(used, info.total)),
+        );
+        values.insert("swap_used", util::human_bytes(swap_used as f64));
+        values.insert("swap_free", util::human_bytes(info.swap_free as f64));
+        values.insert("swap_total", util::human_bytes(info.swap_total as f64));
+        values.insert(
+            "swap_used_percent",
+            format!("{:.0}", percent(swap_used, info.swap_total)),
+        );
+        let markup =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+
+        if self.is_low(info.available) {
+            markup.urgent()
+        } else {
+            markup
+        }
+    }
+
+    fn is_low(&self, available: u64) -> bool {
+        matches!(self.low_available, Some(low) if available < low)
+    }
+}
+
+impl PulledItem for Memory {
+    fn pull(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let info = match self.read_info() {
+            Ok(info) => info,
+            Err(e) => {
+                warn!("Failed to read memory usage: {}", e);
+                *self.text.lock().unwrap() = Markup::error("No memory usage");
+                return Ok(());
+            }
+        };
+        trace!("Updating memory: {} bytes available", info.available);
+        *self.text.lock().unwrap() = self.format_info(&info);
+
+        // Low memory is announced once when it drops below the limit
+        let mut last_available = self.last_available.lock().unwrap();
+        let became_low = self.is_low(info.available)
+            && !matches!(*last_available, Some(last) if self.is_low(last));
+        *last_available = Some(info.available);
+        if self.trigger_show || became_low {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+        Ok(())
+    }
+
+    fn get_interval(&self) -> Duration { self.interval }
+}
+
+impl TextItem for Memory {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok((self.text.lock().unwrap().clone(), self.text_config.clone()))
+    }
+}
+
+impl Item for Memory {}
+
+impl ItemFromConfig for Memory {
+    fn name() -> &'static str { "memory" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(proc_path, config, into_string, "/proc".into());
+        config_get!(format, config, into_string, "{used}/{total}".into());
+        config_get!(interval_sec, config, as_f64, 5.0);
+        config_get!(trigger_show, config, as_bool, false);
+        config_get!(low_available_mib, config, as_i64);
+        util::check_template(&format, FORMAT_KEYS)?;
+        if let Some(low_available_mib) = low_available_mib {
+            ensure!(
+                low_available_mib >= 1,
+                ErrorKind::ConfigError(
+                    "'low-available-mib' must be at least 1".into()
+                )
+            );
+        }
+
+        Ok(Box::new(Memory {
+            proc_path: proc_path.into(),
+            format,
+            interval: util::duration_from_config(
+                "interval-sec",
+                interval_sec,
+            )?,
+            trigger_show,
+            low_available: low_available_mib
+                .map(|mib| mib as u64 * 1024 * 1024),
+            last_available: Mutex::new(None),
+            text: Mutex::new(Markup::default()),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
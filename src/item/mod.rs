@@ -3,13 +3,70 @@
 mod command;
 pub use self::command::{Command, PulledCommand, PushedCommand};
 
+mod clock;
+pub use self::clock::Clock;
+
+mod battery;
+pub use self::battery::Battery;
+
+mod cpu;
+pub use self::cpu::Cpu;
+
+mod memory;
+pub use self::memory::Memory;
+
+mod network;
+pub use self::network::Network;
+
+mod disk;
+pub use self::disk::Disk;
+
+mod temperature;
+pub use self::temperature::Temperature;
+
+mod backlight;
+pub use self::backlight::Backlight;
+
+mod file;
+pub use self::file::File;
+
+mod directory;
+pub use self::directory::Directory;
+
+mod static_text;
+pub use self::static_text::{Separator, StaticText};
+
+mod notifications;
+pub use self::notifications::Notifications;
+
+mod mpd;
+pub use self::mpd::Mpd;
+
+mod workspaces;
+pub use self::workspaces::Workspaces;
+mod active_window;
+pub use self::active_window::ActiveWindow;
+mod timer;
+pub use self::timer::Timer;
+
+mod markup;
+pub use self::markup::{Bar, Content, Markup, Node, Run, Style, Tag};
+
+mod layout;
+pub use self::layout::{Layout, Resources};
+
 mod text_item;
-pub use self::text_item::{TextConfig, TextItem};
+pub use self::text_item::{MinWidth, Overflow, TextConfig, TextItem};
+
+mod i3_ipc;
+mod i3bar;
+mod json_output;
 
 mod pulled;
 pub use self::pulled::PulledItem;
 
 use std::sync::mpsc;
+use std::time::Duration;
 
 use crate::config::{yaml_to_hash_map, Config};
 use crate::error::*;
@@ -17,8 +74,19 @@ use crate::window;
 
 use yaml_rust::Yaml;
 
+/// Time that long-running items wait before trying again after failing, e.g.
+/// to reconnect or to watch a file that didn't exist
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Implementors can be shown on the panel
-pub trait Item: ItemStart + ItemDraw + Send + Sync {}
+pub trait Item: ItemStart + ItemDraw + Send + Sync {
+    /// Handle a click on one of the item's cells
+    fn click(&self, _click: &window::Click) -> Result<()> { Ok(()) }
+
+    /// Handle a command sent over IPC, returning whether the command was for
+    /// this item
+    fn command(&self, _words: &[String]) -> Result<bool> { Ok(false) }
+}
 
 /// Can be started, with the assumption it never terminates
 pub trait ItemStart {
@@ -63,6 +131,40 @@ pub fn parse_items(config: &mut Config) -> Result<Vec<Box<dyn Item>>> {
                 PulledCommand::parse(&mut yaml_object)
             } else if name == PushedCommand::name() {
                 PushedCommand::parse(&mut yaml_object)
+            } else if name == Clock::name() {
+                Clock::parse(&mut yaml_object)
+            } else if name == Battery::name() {
+                Battery::parse(&mut yaml_object)
+            } else if name == Cpu::name() {
+                Cpu::parse(&mut yaml_object)
+            } else if name == Memory::name() {
+                Memory::parse(&mut yaml_object)
+            } else if name == Network::name() {
+                Network::parse(&mut yaml_object)
+            } else if name == Disk::name() {
+                Disk::parse(&mut yaml_object)
+            } else if name == Temperature::name() {
+                Temperature::parse(&mut yaml_object)
+            } else if name == Backlight::name() {
+                Backlight::parse(&mut yaml_object)
+            } else if name == File::name() {
+                File::parse(&mut yaml_object)
+            } else if name == Directory::name() {
+                Directory::parse(&mut yaml_object)
+            } else if name == StaticText::name() {
+                StaticText::parse(&mut yaml_object)
+            } else if name == Separator::name() {
+                Separator::parse(&mut yaml_object)
+            } else if name == Notifications::name() {
+                Notifications::parse(&mut yaml_object)
+            } else if name == Mpd::name() {
+                Mpd::parse(&mut yaml_object)
+            } else if name == Workspaces::name() {
+                Workspaces::parse(&mut yaml_object)
+            } else if name == ActiveWindow::name() {
+                ActiveWindow::parse(&mut yaml_object)
+            } else if name == Timer::name() {
+                Timer::parse(&mut yaml_object)
             } else {
                 Err(ErrorKind::ConfigError(format!(
                     "Unrecognized name: {}",
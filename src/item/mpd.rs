@@ -0,0 +1,486 @@
+//! Shows what's playing in MPD, speaking its protocol. See
+//! <https://mpd.readthedocs.io/en/latest/protocol.html>
+
+use crate::config::Config;
+use crate::error::*;
+use crate::item::text_item;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+    RETRY_INTERVAL,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] =
+    &["artist", "title", "album", "file", "elapsed", "duration", "state"];
+
+/// The song playing in MPD. Changes are received with the `idle` command, so
+/// MPD isn't polled
+pub struct Mpd {
+    /// Either `host:port` or the path of a Unix socket
+    address: String,
+    password: Option<String>,
+    format: String,
+    /// Whether to show the panel when the song changes
+    trigger_show: bool,
+    status: Mutex<Status>,
+    text_config: TextConfig,
+}
+
+enum Status {
+    Disconnected,
+    Stopped,
+    Song(Song),
+}
+
+struct Song {
+    /// Tags from `currentsong`, such as `Artist` and `Title`
+    tags: HashMap<String, String>,
+    playing: bool,
+    /// Time elapsed in the song when the status was received
+    elapsed: Duration,
+    received: Instant,
+    duration: Option<Duration>,
+}
+
+impl Song {
+    /// Get the time elapsed now, as MPD doesn't send updates while playing
+    fn elapsed(&self) -> Duration {
+        if self.playing {
+            self.elapsed + self.received.elapsed()
+        } else {
+            self.elapsed
+        }
+    }
+}
+
+impl Mpd {
+    /// Follow the player until the connection fails
+    fn follow(
+        &self,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let mut connection = Connection::open(&self.address)?;
+        if let Some(password) = &self.password {
+            connection.command(&format!("password {}", quote(password)))?;
+        }
+        info!("Connected to MPD at {}", self.address);
+
+        let mut last_song_id = None;
+        loop {
+            let status = connection.command("status")?;
+            let song_id = status.get("songid").cloned();
+            *self.status.lock().unwrap() =
+                match status.get("state").map(String::as_str) {
+                    Some("play") | Some("pause") => {
+                        let tags = connection.command("currentsong")?;
+                        let seconds = |key: &str| {
+                            status
+                                .get(key)
+                                .and_then(|value| value.parse::<f64>().ok())
+                                .map(Duration::try_from_secs_f64)
+                                .and_then(|duration| duration.ok())
+                        };
+                        Status::Song(Song {
+                            tags,
+                            playing: status["state"] == "play",
+                            elapsed: seconds("elapsed").unwrap_or_default(),
+                            received: Instant::now(),
+                            duration: seconds("duration"),
+                        })
+                    }
+                    _ => Status::Stopped,
+                };
+
+            if song_id.is_some() && song_id != last_song_id {
+                debug!("MPD song changed to {:?}", song_id);
+                if self.trigger_show {
+                    window_command_channel.send(window::Command::Show).unwrap();
+                }
+            }
+            last_song_id = song_id;
+
+            // Block until the player changes, e.g. the song changes, or it's
+            // paused or seeked
+            connection.command("idle player")?;
+        }
+    }
+
+    fn format_song(&self, song: &Song) -> Markup {
+        let tag = |name: &str| song.tags.get(name).cloned().unwrap_or_default();
+        let mut values = HashMap::new();
+        values.insert("artist", tag("Artist"));
+        // Fall back to the file name for untagged songs
+        values.insert(
+            "title",
+            song.tags.get("Title").cloned().unwrap_or_else(|| {
+                let file = tag("file");
+                file.rsplit('/').next().unwrap_or_default().to_string()
+            }),
+        );
+        values.insert("album", tag("Album"));
+        values.insert("file", tag("file"));
+        values.insert("elapsed", format_duration(song.elapsed()));
+        values.insert(
+            "duration",
+            song.duration.map(format_duration).unwrap_or_default(),
+        );
+        values.insert(
+            "state",
+            if song.playing { "playing" } else { "paused" }.into(),
+        );
+        Markup::parse_or_plain(&util::fill_template(&self.format, &values))
+    }
+}
+
+impl ItemStart for Mpd {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        loop {
+            if let Err(e) = self.follow(&window_command_channel) {
+                warn!("Lost connection to MPD: {}", e);
+            }
+            *self.status.lock().unwrap() = Status::Disconnected;
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+}
+
+impl TextItem for Mpd {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok(text_item::first_text(self.get_texts()?, &self.text_config))
+    }
+
+    /// Get the text of the song, formatted when drawing so that the elapsed
+    /// time is up to date. Nothing is drawn when MPD is stopped
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        let markup = match &*self.status.lock().unwrap() {
+            Status::Disconnected => Markup::error("MPD unavailable"),
+            Status::Stopped => return Ok(vec![]),
+            Status::Song(song) => self.format_song(song),
+        };
+        Ok(vec![(markup, self.text_config.clone())])
+    }
+}
+
+impl Item for Mpd {}
+
+impl ItemFromConfig for Mpd {
+    fn name() -> &'static str { "mpd" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(address, config, into_string);
+        config_get!(password, config, into_string);
+        config_get!(format, config, into_string, "{artist} - {title}".into());
+        config_get!(trigger_show, config, as_bool, true);
+        util::check_template(&format, FORMAT_KEYS)?;
+
+        // Default to the same address as MPD's clients, where `MPD_HOST` can
+        // include a password as `password@host`
+        let (address, password) = match address {
+            Some(address) => (address, password),
+            None => {
+                let host =
+                    env::var("MPD_HOST").unwrap_or_else(|_| "localhost".into());
+                let (env_password, host) = match host.rfind('@') {
+                    Some(i) => {
+                        (Some(host[..i].to_string()), host[i + 1..].into())
+                    }
+                    None => (None, host),
+                };
+                let address = if host.starts_with('/') {
+                    host
+                } else {
+                    let port =
+                        env::var("MPD_PORT").unwrap_or_else(|_| "6600".into());
+                    format!("{}:{}", host, port)
+                };
+                (address, password.or(env_password))
+            }
+        };
+
+        Ok(Box::new(Mpd {
+            address,
+            password,
+            format,
+            trigger_show,
+            status: Mutex::new(Status::Disconnected),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Connection to MPD over TCP or a Unix socket
+struct Connection {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl Connection {
+    /// Connect and check MPD's greeting
+    fn open(address: &str) -> Result<Self> {
+        let connect_error =
+            || format!("Failed to connect to MPD at {}", address);
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) =
+            if address.starts_with('/') {
+                let stream =
+                    UnixStream::connect(address).chain_err(connect_error)?;
+                (
+                    Box::new(stream.try_clone().chain_err(connect_error)?),
+                    Box::new(stream),
+                )
+            } else {
+                let stream =
+                    TcpStream::connect(address).chain_err(connect_error)?;
+                (
+                    Box::new(stream.try_clone().chain_err(connect_error)?),
+                    Box::new(stream),
+                )
+            };
+        let mut connection = Connection {
+            reader: BufReader::new(reader),
+            writer,
+        };
+
+        let greeting = connection.read_line()?;
+        ensure!(
+            greeting.starts_with("OK MPD "),
+            ErrorKind::CommandError(format!(
+                "Unexpected greeting from MPD: {}",
+                greeting
+            ))
+        );
+        Ok(connection)
+    }
+
+    /// Send a command, and get the `key: value` pairs in its response
+    fn command(&mut self, command: &str) -> Result<HashMap<String, String>> {
+        trace!("Sending MPD command: {}", command);
+        writeln!(self.writer, "{}", command)
+            .and_then(|_| self.writer.flush())
+            .chain_err(|| "Failed to send MPD command")?;
+
+        let mut response = HashMap::new();
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(response);
+            }
+            if line.starts_with("ACK ") {
+                bail!(ErrorKind::CommandError(format!(
+                    "MPD command {} failed: {}",
+                    command, line
+                )));
+            }
+            if let Some(separator) = line.find(": ") {
+                response.insert(
+                    line[..separator].to_string(),
+                    line[separator + 2..].to_string(),
+                );
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        let read = self
+            .reader
+            .read_line(&mut line)
+            .chain_err(|| "Failed to read from MPD")?;
+        ensure!(
+            read > 0,
+            ErrorKind::CommandError("MPD closed the connection".into())
+        );
+        Ok(line.trim_end_matches('\n').to_string())
+    }
+}
+
+/// Quote an argument to an MPD command
+fn quote(argument: &str) -> String {
+    format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Format a duration as minutes and seconds, e.g. `3:07`
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::{Content, Style};
+
+    use std::os::unix::net::UnixListener;
+    use std::sync::Arc;
+
+    use sfml::graphics::Color;
+    use tempfile::TempDir;
+
+    const BASE_STYLE: Style = Style {
+        color: Color::WHITE,
+        bold: false,
+        italic: false,
+        size: 12,
+    };
+
+    /// Start a fake MPD server that answers each expected command in order
+    /// with its response. `idle` commands are answered when `wakes` receives,
+    /// and the connection is closed when the script ends or `wakes` is
+    /// disconnected
+    fn serve(
+        dir: &TempDir,
+        greeting: &'static str,
+        script: Vec<(&'static str, &'static str)>,
+        wakes: mpsc::Receiver<()>,
+    ) -> String
+    {
+        let path = dir.path().join("mpd.socket");
+        let listener = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            writer.write_all(greeting.as_bytes()).unwrap();
+            for (expected, response) in script {
+                let command = match lines.next() {
+                    Some(Ok(command)) => command,
+                    _ => return,
+                };
+                assert_eq!(command, expected);
+                if command.starts_with("idle") && wakes.recv().is_err() {
+                    return;
+                }
+                writer.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        path.to_string_lossy().into_owned()
+    }
+
+    fn mpd(address: String) -> Mpd {
+        Mpd {
+            address,
+            password: Some("se\"cret".into()),
+            format: "{artist} - {title} ({state}, {duration})".into(),
+            trigger_show: true,
+            status: Mutex::new(Status::Disconnected),
+            text_config: TextConfig::parse(&mut Config::new()).unwrap(),
+        }
+    }
+
+    fn text(mpd: &Mpd) -> String {
+        mpd.get_texts()
+            .unwrap()
+            .iter()
+            .flat_map(|(markup, _)| markup.runs(BASE_STYLE))
+            .filter_map(|run| match run.content {
+                Content::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn connection_reads_responses() {
+        let dir = TempDir::new().unwrap();
+        let (_wake_send, wakes) = mpsc::channel();
+        let address = serve(
+            &dir,
+            "OK MPD 0.23.5\n",
+            vec![
+                ("status", "volume: 50\nstate: stop\nOK\n"),
+                ("bogus", "ACK [5@0] {bogus} unknown command \"bogus\"\n"),
+            ],
+            wakes,
+        );
+        let mut connection = Connection::open(&address).unwrap();
+        let status = connection.command("status").unwrap();
+        assert_eq!(status.len(), 2);
+        assert_eq!(status["volume"], "50");
+        assert_eq!(status["state"], "stop");
+
+        let error = connection.command("bogus").unwrap_err();
+        assert!(error.to_string().contains("unknown command"));
+    }
+
+    #[test]
+    fn connection_rejects_unexpected_greeting() {
+        let dir = TempDir::new().unwrap();
+        let (_wake_send, wakes) = mpsc::channel();
+        let address = serve(&dir, "HTTP/1.1 400\n", vec![], wakes);
+        assert!(Connection::open(&address).is_err());
+    }
+
+    #[test]
+    fn follow_shows_panel_when_song_changes() {
+        let dir = TempDir::new().unwrap();
+        let (wake_send, wakes) = mpsc::channel();
+        let address = serve(
+            &dir,
+            "OK MPD 0.23.5\n",
+            vec![
+                ("password \"se\\\"cret\"", "OK\n"),
+                // Invalid times from MPD are ignored
+                (
+                    "status",
+                    "state: play\nsongid: 1\nelapsed: -1\nduration: 125\nOK\n",
+                ),
+                ("currentsong", "Artist: A\nTitle: First\nOK\n"),
+                ("idle player", "changed: player\nOK\n"),
+                ("status", "state: play\nsongid: 2\nduration: 61.0\nOK\n"),
+                ("currentsong", "Artist: B\nfile: music/second.ogg\nOK\n"),
+                ("idle player", "changed: player\nOK\n"),
+                ("status", "state: pause\nsongid: 2\nduration: 61.0\nOK\n"),
+                ("currentsong", "Artist: B\nfile: music/second.ogg\nOK\n"),
+                ("idle player", "changed: player\nOK\n"),
+            ],
+            wakes,
+        );
+        let mpd = Arc::new(mpd(address));
+        let (window_command_channel, window_commands) = mpsc::channel();
+        let follower = {
+            let mpd = mpd.clone();
+            thread::spawn(move || mpd.follow(&window_command_channel))
+        };
+        let show = || {
+            let command = window_commands.recv_timeout(Duration::from_secs(5));
+            matches!(command, Ok(window::Command::Show))
+        };
+
+        // The first song is announced when connecting
+        assert!(show());
+        assert_eq!(text(&mpd), "A - First (playing, 2:05)");
+
+        wake_send.send(()).unwrap();
+        assert!(show());
+        assert_eq!(text(&mpd), "B - second.ogg (playing, 1:01)");
+
+        // Pausing isn't a song change
+        wake_send.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !text(&mpd).contains("paused") {
+            assert!(Instant::now() < deadline, "Timed out waiting for pause");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let command = window_commands.recv_timeout(Duration::from_millis(200));
+        assert!(command.is_err());
+
+        // Losing the connection ends following
+        drop(wake_send);
+        assert!(follower.join().unwrap().is_err());
+    }
+}
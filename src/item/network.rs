@@ -0,0 +1,344 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::text_item;
+use crate::item::{
+    Item, ItemFromConfig, Markup, PulledItem, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+use glob::Pattern;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["interface", "rx", "tx", "rx_total", "tx_total"];
+
+/// Network throughput of each interface, read from `/proc/net/dev`. Each
+/// interface is drawn in its own grid cell
+pub struct Network {
+    proc_path: PathBuf,
+    /// Interfaces to show, or all interfaces except loopback if `None`
+    interface: Option<Pattern>,
+    format: String,
+    interval: Duration,
+    trigger_show: bool,
+    /// Counters from the last pull, which rates are calculated relative to
+    last_counters: Mutex<HashMap<String, Counters>>,
+    texts: Mutex<Vec<Markup>>,
+    text_config: TextConfig,
+}
+
+/// Bytes received and transmitted by an interface
+#[derive(Clone, Copy)]
+struct Counters {
+    rx: u64,
+    tx: u64,
+    time: Instant,
+}
+
+impl Network {
+    /// Read the counters of each interface, sorted by name
+    fn read_counters(&self) -> Result<Vec<(String, Counters)>> {
+        let dev_path = self.proc_path.join("net/dev");
+        let dev = fs::read_to_string(&dev_path)
+            .chain_err(|| format!("Failed to read {}", dev_path.display()))?;
+        let time = Instant::now();
+
+        // Skip the two header lines. Other lines are of the form
+        // `  eth0: <8 receive fields> <8 transmit fields>`
+        let mut counters = dev
+            .lines()
+            .skip(2)
+            .filter_map(|line| {
+                let colon = line.rfind(':')?;
+                let interface = line[..colon].trim();
+                let fields = line[colon + 1..]
+                    .split_whitespace()
+                    .map(|field| field.parse::<u64>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                let counters = Counters {
+                    rx: *fields.first()?,
+                    tx: *fields.get(8)?,
+                    time,
+                };
+                Some((interface.to_string(), counters))
+            })
+            .filter(|(interface, _)| match &self.interface {
+                Some(pattern) => pattern.matches(interface),
+                None => interface != "lo",
+            })
+            .collect::<Vec<_>>();
+        counters.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(counters)
+    }
+
+    fn format_counters(
+        &self,
+        interface: &str,
+        counters: &Counters,
+        last: Option<&Counters>,
+    ) -> Markup
+    {
+        // Interfaces that have just appeared have no rate yet, and counters
+        // restart from zero when an interface is recreated
+        let rate = |now: u64, last: Option<u64>, elapsed: f64| match last {
+            Some(last) if elapsed > 0.0 => {
+                now.saturating_sub(last) as f64 / elapsed
+            }
+            _ => 0.0,
+        };
+        let elapsed = last.map_or(0.0, |last| {
+            counters.time.duration_since(last.time).as_secs_f64()
+        });
+        let rx = rate(counters.rx, last.map(|last| last.rx), elapsed);
+        let tx = rate(counters.tx, last.map(|last| last.tx), elapsed);
+
+        let mut values = HashMap::new();
+        values.insert("interface", interface.to_string());
+        values.insert("rx", format!("{}/s", util::human_bytes(rx)));
+        values.insert("tx", format!("{}/s", util::human_bytes(tx)));
+        values.insert("rx_total", util::human_bytes(counters.rx as f64));
+        values.insert("tx_total", util::human_bytes(counters.tx as f64));
+        Markup::parse_or_plain(&util::fill_template(&self.format, &values))
+    }
+}
+
+impl PulledItem for Network {
+    fn pull(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let counters = match self.read_counters() {
+            Ok(counters) => counters,
+            Err(e) => {
+                warn!("Failed to read network usage: {}", e);
+                *self.texts.lock().unwrap() =
+                    vec![Markup::error("No network usage")];
+                return Ok(());
+            }
+        };
+        trace!("Updating network: {} interfaces", counters.len());
+
+        let mut last_counters = self.last_counters.lock().unwrap();
+        *self.texts.lock().unwrap() = counters
+            .iter()
+            .map(|(interface, counters)| {
+                self.format_counters(
+                    interface,
+                    counters,
+                    last_counters.get(interface),
+                )
+            })
+            .collect();
+        // Interfaces that have disappeared are dropped here
+        *last_counters = counters.into_iter().collect();
+
+        if self.trigger_show {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+        Ok(())
+    }
+
+    fn get_interval(&self) -> Duration { self.interval }
+}
+
+impl TextItem for Network {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok(text_item::first_text(self.get_texts()?, &self.text_config))
+    }
+
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        Ok(text_item::cell_texts(&self.texts, &self.text_config))
+    }
+}
+
+impl Item for Network {}
+
+impl ItemFromConfig for Network {
+    fn name() -> &'static str { "network" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(proc_path, config, into_string, "/proc".into());
+        config_get!(interface, config, into_string);
+        config_get!(
+            format,
+            config,
+            into_string,
+            "{interface}: {rx} down, {tx} up".into()
+        );
+        config_get!(interval_sec, config, as_f64, 2.0);
+        config_get!(trigger_show, config, as_bool, false);
+        util::check_template(&format, FORMAT_KEYS)?;
+
+        let interface = match interface {
+            Some(interface) => Some(Pattern::new(&interface).chain_err(
+                || format!("Invalid interface pattern: {}", interface),
+            )?),
+            None => None,
+        };
+
+        Ok(Box::new(Network {
+            proc_path: proc_path.into(),
+            interface,
+            format,
+            interval: util::duration_from_config(
+                "interval-sec",
+                interval_sec,
+            )?,
+            trigger_show,
+            last_counters: Mutex::new(HashMap::new()),
+            texts: Mutex::new(vec![]),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::{Content, Style};
+
+    use sfml::graphics::Color;
+    use tempfile::TempDir;
+
+    const BASE_STYLE: Style = Style {
+        color: Color::WHITE,
+        bold: false,
+        italic: false,
+        size: 12,
+    };
+
+    const HEADER: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    \
+packets errs drop fifo colls carrier compressed
+";
+
+    fn network(proc_path: &TempDir) -> Network {
+        Network {
+            proc_path: proc_path.path().into(),
+            interface: None,
+            format: "{interface} {rx} {tx}".into(),
+            interval: Duration::from_secs(2),
+            trigger_show: false,
+            last_counters: Mutex::new(HashMap::new()),
+            texts: Mutex::new(vec![]),
+            text_config: TextConfig::parse(&mut Config::new()).unwrap(),
+        }
+    }
+
+    /// Write `/proc/net/dev` with the bytes received and transmitted by each
+    /// interface
+    fn write_dev(proc_path: &TempDir, interfaces: &[(&str, u64, u64)]) {
+        let mut dev = HEADER.to_string();
+        for (interface, rx, tx) in interfaces {
+            dev += &format!(
+                "{:>6}: {} 10 0 0 0 0 0 0 {} 10 0 0 0 0 0 0\n",
+                interface, rx, tx
+            );
+        }
+        let net_path = proc_path.path().join("net");
+        fs::create_dir_all(&net_path).unwrap();
+        fs::write(net_path.join("dev"), dev).unwrap();
+    }
+
+    fn text(markup: &Markup) -> String {
+        markup
+            .runs(BASE_STYLE)
+            .into_iter()
+            .filter_map(|run| match run.content {
+                Content::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Pull the network usage, returning the text of each interface
+    fn pull(network: &Network) -> Vec<String> {
+        let (window_command_channel, _window_commands) = mpsc::channel();
+        network.pull(window_command_channel).unwrap();
+        network.texts.lock().unwrap().iter().map(text).collect()
+    }
+
+    #[test]
+    fn read_counters_skips_loopback_and_sorts() {
+        let proc_path = TempDir::new().unwrap();
+        write_dev(&proc_path, &[
+            ("wlan0", 300, 400),
+            ("lo", 50, 50),
+            ("eth0", 100, 200),
+        ]);
+        let counters = network(&proc_path).read_counters().unwrap();
+        let counters: Vec<_> = counters
+            .iter()
+            .map(|(interface, c)| (interface.as_str(), c.rx, c.tx))
+            .collect();
+        assert_eq!(counters, [("eth0", 100, 200), ("wlan0", 300, 400)]);
+    }
+
+    #[test]
+    fn read_counters_filters_by_pattern() {
+        let proc_path = TempDir::new().unwrap();
+        write_dev(&proc_path, &[("eth0", 1, 2), ("wlan0", 3, 4)]);
+        let mut network = network(&proc_path);
+        network.interface = Some(Pattern::new("wl*").unwrap());
+        let counters = network.read_counters().unwrap();
+        assert_eq!(counters.len(), 1);
+        assert_eq!(counters[0].0, "wlan0");
+    }
+
+    #[test]
+    fn format_counters_calculates_rates() {
+        let proc_path = TempDir::new().unwrap();
+        let network = network(&proc_path);
+        let last = Counters {
+            rx: 1000,
+            tx: 5000,
+            time: Instant::now(),
+        };
+        let counters = Counters {
+            rx: 1000 + 2 * 2048,
+            tx: 5000 + 2 * 100,
+            time: last.time + Duration::from_secs(2),
+        };
+        let markup = network.format_counters("eth0", &counters, Some(&last));
+        assert_eq!(text(&markup), "eth0 2.0 KiB/s 100 B/s");
+
+        // Counters restart when an interface is recreated
+        let markup = network.format_counters("eth0", &last, Some(&counters));
+        assert_eq!(text(&markup), "eth0 0 B/s 0 B/s");
+
+        let markup = network.format_counters("eth0", &counters, None);
+        assert_eq!(text(&markup), "eth0 0 B/s 0 B/s");
+    }
+
+    #[test]
+    fn pull_follows_interfaces_appearing_and_disappearing() {
+        let proc_path = TempDir::new().unwrap();
+        let network = network(&proc_path);
+        write_dev(&proc_path, &[("eth0", 100, 100)]);
+        assert_eq!(pull(&network), ["eth0 0 B/s 0 B/s"]);
+
+        write_dev(&proc_path, &[("eth0", 100, 100), ("wlan0", 500, 500)]);
+        assert_eq!(pull(&network), ["eth0 0 B/s 0 B/s", "wlan0 0 B/s 0 B/s"]);
+
+        write_dev(&proc_path, &[("wlan0", 500, 500)]);
+        assert_eq!(pull(&network), ["wlan0 0 B/s 0 B/s"]);
+        let last_counters = network.last_counters.lock().unwrap();
+        assert!(!last_counters.contains_key("eth0"));
+        assert!(last_counters.contains_key("wlan0"));
+    }
+
+    #[test]
+    fn pull_shows_error_without_counters() {
+        let proc_path = TempDir::new().unwrap();
+        let network = network(&proc_path);
+        assert_eq!(pull(&network), ["No network usage"]);
+    }
+}
@@ -0,0 +1,597 @@
+//! Implements the desktop notifications specification over D-Bus, so that
+//! incredi can be used as a notification daemon. See
+//! <https://specifications.freedesktop.org/notification-spec/latest/>
+
+use crate::config::Config;
+use crate::error::*;
+use crate::item::text_item;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, Node, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use zbus::blocking::connection;
+use zbus::names::BusName;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedValue;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["app", "summary", "body", "urgency"];
+
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+
+/// Reasons for closing a notification, sent in `NotificationClosed`
+const CLOSED_EXPIRED: u32 = 1;
+const CLOSED_DISMISSED: u32 = 2;
+const CLOSED_BY_CALL: u32 = 3;
+
+/// Urgency of critical notifications, which don't expire by default
+const URGENCY_CRITICAL: u8 = 2;
+
+/// Notifications received over D-Bus, newest first. Each notification is drawn
+/// in its own grid cell until it expires or is clicked
+pub struct Notifications {
+    format: String,
+    /// Timeout of notifications that don't specify one, or `None` to never
+    /// expire them
+    default_timeout: Option<Duration>,
+    show_icons: bool,
+    notifications: Arc<Mutex<Vec<Notification>>>,
+    /// Connection to the session bus, once the server has started
+    connection: Mutex<Option<connection::Connection>>,
+    text_config: TextConfig,
+}
+
+struct Notification {
+    id: u32,
+    markup: Markup,
+    /// When to remove the notification, or `None` to keep it until it's
+    /// closed
+    expires: Option<Instant>,
+}
+
+impl ItemStart for Notifications {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let (wake_send, wake_receive) = mpsc::channel();
+        let server = Server {
+            format: self.format.clone(),
+            default_timeout: self.default_timeout,
+            show_icons: self.show_icons,
+            notifications: self.notifications.clone(),
+            next_id: AtomicU32::new(1),
+            window_command_channel,
+            wake: Mutex::new(wake_send),
+        };
+        let connection = connection::Builder::session()
+            .and_then(|builder| builder.name(BUS_NAME))
+            .and_then(|builder| builder.serve_at(OBJECT_PATH, server))
+            .and_then(|builder| builder.build())
+            .chain_err(|| {
+                format!(
+                    "Failed to start notification server, is another \
+                     notification daemon running as {}?",
+                    BUS_NAME
+                )
+            })?;
+        info!("Started notification server");
+        *self.connection.lock().unwrap() = Some(connection.clone());
+
+        // Remove expired notifications, waking early when a notification is
+        // added in case it expires sooner
+        loop {
+            let next_expiry = self
+                .notifications
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|notification| notification.expires)
+                .min();
+            // The server keeps the sender alive, so this only returns on a
+            // wake or timeout
+            let _ = match next_expiry {
+                Some(next_expiry) => wake_receive.recv_timeout(
+                    next_expiry.saturating_duration_since(Instant::now()),
+                ),
+                None => wake_receive.recv().map_err(Into::into),
+            };
+
+            let now = Instant::now();
+            let mut expired = vec![];
+            self.notifications.lock().unwrap().retain(|notification| {
+                let is_expired =
+                    matches!(notification.expires, Some(e) if e <= now);
+                if is_expired {
+                    expired.push(notification.id);
+                }
+                !is_expired
+            });
+            for id in expired {
+                trace!("Notification {} expired", id);
+                emit_closed(&connection, id, CLOSED_EXPIRED);
+            }
+        }
+    }
+}
+
+impl TextItem for Notifications {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok(text_item::first_text(self.get_texts()?, &self.text_config))
+    }
+
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        Ok(self
+            .notifications
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|notification| {
+                (notification.markup.clone(), self.text_config.clone())
+            })
+            .collect())
+    }
+}
+
+impl Item for Notifications {
+    /// Dismiss the clicked notification
+    fn click(&self, click: &window::Click) -> Result<()> {
+        let id = {
+            let mut notifications = self.notifications.lock().unwrap();
+            if click.cell >= notifications.len() {
+                return Ok(());
+            }
+            notifications.remove(click.cell).id
+        };
+        debug!("Dismissed notification {}", id);
+        if let Some(connection) = self.connection.lock().unwrap().as_ref() {
+            emit_closed(connection, id, CLOSED_DISMISSED);
+        }
+        Ok(())
+    }
+}
+
+impl ItemFromConfig for Notifications {
+    fn name() -> &'static str { "notifications" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(
+            format,
+            config,
+            into_string,
+            "<b>{summary}</b>\n{body}".into()
+        );
+        config_get!(default_timeout_sec, config, as_f64, 5.0);
+        config_get!(show_icons, config, as_bool, true);
+        util::check_template(&format, FORMAT_KEYS)?;
+        // A timeout of zero means that notifications never expire
+        let default_timeout = if default_timeout_sec == 0.0 {
+            None
+        } else {
+            Some(util::duration_from_config(
+                "default-timeout-sec",
+                default_timeout_sec,
+            )?)
+        };
+
+        Ok(Box::new(Notifications {
+            format,
+            default_timeout,
+            show_icons,
+            notifications: Arc::new(Mutex::new(vec![])),
+            connection: Mutex::new(None),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Serves the `org.freedesktop.Notifications` interface
+struct Server {
+    format: String,
+    default_timeout: Option<Duration>,
+    show_icons: bool,
+    notifications: Arc<Mutex<Vec<Notification>>>,
+    next_id: AtomicU32,
+    window_command_channel: mpsc::Sender<window::Command>,
+    /// Wakes the item's thread to check for expired notifications
+    wake: Mutex<mpsc::Sender<()>>,
+}
+
+impl Server {
+    fn format_notification(
+        &self,
+        app_name: &str,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        urgency: u8,
+        hints: &HashMap<String, OwnedValue>,
+    ) -> Markup
+    {
+        let mut values = HashMap::new();
+        values.insert("app", app_name.to_string());
+        values.insert("summary", summary.to_string());
+        values.insert("body", body.to_string());
+        values.insert(
+            "urgency",
+            match urgency {
+                0 => "low",
+                URGENCY_CRITICAL => "critical",
+                _ => "normal",
+            }
+            .into(),
+        );
+        // Trim so that an empty body doesn't add an empty line
+        let mut markup = Markup::parse_or_plain(
+            util::fill_template(&self.format, &values).trim(),
+        );
+        if urgency == URGENCY_CRITICAL {
+            markup = markup.urgent();
+        }
+
+        // Only icons given as paths are supported, not icon theme names
+        let image_path = hints
+            .get("image-path")
+            .and_then(|value| <&str>::try_from(value).ok())
+            .unwrap_or(app_icon);
+        let image_path = image_path.trim_start_matches("file://");
+        if self.show_icons && image_path.starts_with('/') {
+            markup = Markup::concat(vec![
+                Markup::from_nodes(vec![Node::Image(image_path.into())]),
+                Markup::plain(" "),
+                markup,
+            ]);
+        }
+        markup
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl Server {
+    fn get_capabilities(&self) -> Vec<String> {
+        vec!["body".into(), "icon-static".into(), "persistence".into()]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        _actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32
+    {
+        debug!("Received notification from {}: {}", app_name, summary);
+        let urgency = hints
+            .get("urgency")
+            .and_then(|value| u8::try_from(value).ok())
+            .unwrap_or(1);
+        let markup = self.format_notification(
+            &app_name, &app_icon, &summary, &body, urgency, &hints,
+        );
+        // A timeout of -1 means the server's default, and 0 means never
+        let timeout = match expire_timeout {
+            t if t > 0 => Some(Duration::from_millis(t as u64)),
+            0 => None,
+            _ if urgency == URGENCY_CRITICAL => None,
+            _ => self.default_timeout,
+        };
+
+        let mut notifications = self.notifications.lock().unwrap();
+        let replaced = notifications
+            .iter_mut()
+            .find(|notification| notification.id == replaces_id);
+        let id = match replaced {
+            Some(notification) if replaces_id != 0 => {
+                notification.markup = markup;
+                notification.expires = timeout.map(|t| Instant::now() + t);
+                replaces_id
+            }
+            _ => {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                notifications.insert(
+                    0,
+                    Notification {
+                        id,
+                        markup,
+                        expires: timeout.map(|t| Instant::now() + t),
+                    },
+                );
+                id
+            }
+        };
+        drop(notifications);
+
+        self.wake.lock().unwrap().send(()).unwrap();
+        self.window_command_channel
+            .send(window::Command::Show)
+            .unwrap();
+        id
+    }
+
+    async fn close_notification(
+        &self,
+        id: u32,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()>
+    {
+        let removed = {
+            let mut notifications = self.notifications.lock().unwrap();
+            let count = notifications.len();
+            notifications.retain(|notification| notification.id != id);
+            notifications.len() != count
+        };
+        if removed {
+            Self::notification_closed(&emitter, id, CLOSED_BY_CALL).await?;
+        }
+        Ok(())
+    }
+
+    fn get_server_information(&self) -> (String, String, String, String) {
+        (
+            "incredi".into(),
+            "incredi-panel".into(),
+            env!("CARGO_PKG_VERSION").into(),
+            "1.2".into(),
+        )
+    }
+
+    #[zbus(signal)]
+    async fn notification_closed(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+}
+
+/// Tell clients that a notification was closed
+fn emit_closed(connection: &connection::Connection, id: u32, reason: u32) {
+    let result = connection.emit_signal(
+        None::<BusName<'_>>,
+        OBJECT_PATH,
+        BUS_NAME,
+        "NotificationClosed",
+        &(id, reason),
+    );
+    if let Err(e) = result {
+        warn!("Failed to send NotificationClosed signal: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::{Content, Style};
+
+    use sfml::graphics::Color;
+    use zbus::zvariant::Value;
+
+    const BASE_STYLE: Style = Style {
+        color: Color::WHITE,
+        bold: false,
+        italic: false,
+        size: 12,
+    };
+
+    /// Create a server, along with the receivers of its window commands and
+    /// wakes
+    fn server(
+        default_timeout: Option<Duration>,
+    ) -> (Server, mpsc::Receiver<window::Command>, mpsc::Receiver<()>)
+    {
+        let (window_command_channel, window_commands) = mpsc::channel();
+        let (wake_send, wake_receive) = mpsc::channel();
+        let server = Server {
+            format: "<b>{summary}</b>\n{body}".into(),
+            default_timeout,
+            show_icons: true,
+            notifications: Arc::new(Mutex::new(vec![])),
+            next_id: AtomicU32::new(1),
+            window_command_channel,
+            wake: Mutex::new(wake_send),
+        };
+        (server, window_commands, wake_receive)
+    }
+
+    fn hint(value: Value) -> OwnedValue { OwnedValue::try_from(value).unwrap() }
+
+    /// Get the content of each run, with images shown as `[path]`, and
+    /// whether each run is bold
+    fn contents(markup: &Markup) -> Vec<(String, bool)> {
+        markup
+            .runs(BASE_STYLE)
+            .into_iter()
+            .map(|run| {
+                let content = match run.content {
+                    Content::Text(text) => text,
+                    Content::Image(src) => format!("[{}]", src),
+                    _ => panic!("Unexpected run"),
+                };
+                (content, run.style.bold)
+            })
+            .filter(|(content, _)| !content.is_empty())
+            .collect()
+    }
+
+    fn notify(
+        server: &Server,
+        replaces_id: u32,
+        summary: &str,
+        expire_timeout: i32,
+    ) -> u32
+    {
+        server.notify(
+            "app".into(),
+            replaces_id,
+            String::new(),
+            summary.into(),
+            String::new(),
+            vec![],
+            HashMap::new(),
+            expire_timeout,
+        )
+    }
+
+    #[test]
+    fn format_notification_fills_format() {
+        let (server, _window_commands, _wakes) = server(None);
+        let markup = server.format_notification(
+            "app",
+            "",
+            "Hello",
+            "a < b",
+            1,
+            &HashMap::new(),
+        );
+        assert_eq!(contents(&markup), [
+            ("Hello".into(), true),
+            ("\na < b".into(), false),
+        ]);
+    }
+
+    #[test]
+    fn format_notification_trims_empty_body() {
+        let (server, _window_commands, _wakes) = server(None);
+        let hints = HashMap::new();
+        let markup =
+            server.format_notification("app", "", "Hello", "", 1, &hints);
+        assert_eq!(contents(&markup), [("Hello".into(), true)]);
+    }
+
+    #[test]
+    fn format_notification_marks_critical_as_urgent() {
+        let (server, _window_commands, _wakes) = server(None);
+        let markup = server.format_notification(
+            "app",
+            "",
+            "Hello",
+            "",
+            URGENCY_CRITICAL,
+            &HashMap::new(),
+        );
+        let runs = markup.runs(BASE_STYLE);
+        let (r, g, b) = (255, 80, 80);
+        assert!(runs.iter().all(|run| run.style.color == Color::rgb(r, g, b)));
+    }
+
+    #[test]
+    fn format_notification_shows_image_paths() {
+        let (mut server, _window_commands, _wakes) = server(None);
+        let mut hints = HashMap::new();
+        hints.insert(
+            "image-path".to_string(),
+            hint(Value::from("file:///tmp/image.png")),
+        );
+        let markup = server
+            .format_notification("app", "dialog-info", "Hi", "", 1, &hints);
+        assert_eq!(contents(&markup), [
+            ("[/tmp/image.png]".into(), false),
+            (" ".into(), false),
+            ("Hi".into(), true),
+        ]);
+
+        // Icon theme names aren't supported
+        let markup = server.format_notification(
+            "app",
+            "dialog-info",
+            "Hi",
+            "",
+            1,
+            &HashMap::new(),
+        );
+        assert_eq!(contents(&markup), [("Hi".into(), true)]);
+
+        server.show_icons = false;
+        let markup = server
+            .format_notification("app", "/tmp/icon.png", "Hi", "", 1, &hints);
+        assert_eq!(contents(&markup), [("Hi".into(), true)]);
+    }
+
+    #[test]
+    fn notify_adds_newest_first_and_shows_panel() {
+        let (server, window_commands, wakes) = server(None);
+        assert_eq!(notify(&server, 0, "first", 0), 1);
+        assert_eq!(notify(&server, 0, "second", 0), 2);
+        let notifications = server.notifications.lock().unwrap();
+        let ids: Vec<_> = notifications.iter().map(|n| n.id).collect();
+        assert_eq!(ids, [2, 1]);
+        assert_eq!(window_commands.try_iter().count(), 2);
+        assert_eq!(wakes.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn notify_replaces_existing_notification() {
+        let (server, _window_commands, _wakes) = server(None);
+        notify(&server, 0, "first", 0);
+        notify(&server, 0, "second", 0);
+        assert_eq!(notify(&server, 1, "replaced", 0), 1);
+        let notifications = server.notifications.lock().unwrap();
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(contents(&notifications[1].markup), [(
+            "replaced".into(),
+            true
+        )]);
+    }
+
+    #[test]
+    fn notify_adds_notification_if_replaced_id_is_unknown() {
+        let (server, _window_commands, _wakes) = server(None);
+        assert_eq!(notify(&server, 7, "first", 0), 1);
+        assert_eq!(server.notifications.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn notify_sets_expiry() {
+        let default_timeout = Duration::from_secs(5);
+        let (server, _window_commands, _wakes) = server(Some(default_timeout));
+        let before = Instant::now();
+        notify(&server, 0, "given", 2000);
+        notify(&server, 0, "never", 0);
+        notify(&server, 0, "default", -1);
+        let after = Instant::now();
+
+        let notifications = server.notifications.lock().unwrap();
+        let expires: Vec<_> = notifications.iter().map(|n| n.expires).collect();
+        let within = |expires: Option<Instant>, timeout: Duration| {
+            let expires = expires.unwrap();
+            before + timeout <= expires && expires <= after + timeout
+        };
+        assert!(within(expires[0], default_timeout));
+        assert!(expires[1].is_none());
+        assert!(within(expires[2], Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn notify_keeps_critical_notifications_by_default() {
+        let (server, _window_commands, _wakes) =
+            server(Some(Duration::from_secs(5)));
+        let mut hints = HashMap::new();
+        let urgency = hint(Value::from(URGENCY_CRITICAL));
+        hints.insert("urgency".to_string(), urgency);
+        server.notify(
+            "app".into(),
+            0,
+            String::new(),
+            "critical".into(),
+            String::new(),
+            vec![],
+            hints,
+            -1,
+        );
+        assert!(server.notifications.lock().unwrap()[0].expires.is_none());
+    }
+}
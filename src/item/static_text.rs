@@ -0,0 +1,109 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemDraw, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window::{self, DrawConfig, DrawableConfig, Window};
+
+use std::sync::mpsc;
+
+use sfml::graphics::{Color, RectangleShape, Shape};
+use sfml::system::Vector2f;
+
+/// Text that never changes, such as a section header
+pub struct StaticText {
+    text: Markup,
+    text_config: TextConfig,
+}
+
+impl ItemStart for StaticText {
+    fn start(
+        &self,
+        _window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        Ok(())
+    }
+}
+
+impl TextItem for StaticText {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok((self.text.clone(), self.text_config.clone()))
+    }
+}
+
+impl Item for StaticText {}
+
+impl ItemFromConfig for StaticText {
+    fn name() -> &'static str { "text" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(text, config, into_string, required);
+        // The text is known up front, so invalid markup is a config error
+        // rather than falling back to plain text
+        let text = Markup::parse(&text)
+            .chain_err(|| format!("Failed to parse text: {}", text))?;
+        Ok(Box::new(StaticText {
+            text,
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Horizontal line spanning the width of the grid
+pub struct Separator {
+    color: Color,
+    /// Thickness of the line in pixels
+    thickness: u32,
+    draw_config: DrawConfig,
+}
+
+impl ItemStart for Separator {
+    fn start(
+        &self,
+        _window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        Ok(())
+    }
+}
+
+impl ItemDraw for Separator {
+    fn draw(&self, window: &mut Window) -> Result<()> {
+        let width = (window.config.grid_width * window.config.grid_size)
+            .saturating_sub(self.draw_config.horizontal_padding * 2);
+        let mut line = RectangleShape::with_size(Vector2f::new(
+            width as f32,
+            self.thickness as f32,
+        ));
+        line.set_fill_color(&self.color);
+        window.draw(
+            vec![DrawableConfig::new(&line)],
+            self.draw_config.clone(),
+            width,
+            self.thickness,
+        );
+        Ok(())
+    }
+}
+
+impl Item for Separator {}
+
+impl ItemFromConfig for Separator {
+    fn name() -> &'static str { "separator" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(color, config, into_string, "ffffff".into());
+        config_get!(thickness, config, as_i64, 2);
+        ensure!(
+            thickness >= 1,
+            ErrorKind::ConfigError("'thickness' must be at least 1".into())
+        );
+        Ok(Box::new(Separator {
+            color: util::hex_color(&color)?,
+            thickness: thickness as u32,
+            draw_config: DrawConfig::parse(config)?,
+        }))
+    }
+}
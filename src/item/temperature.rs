@@ -0,0 +1,320 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::text_item;
+use crate::item::{
+    Item, ItemFromConfig, Markup, PulledItem, Tag, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use glob::Pattern;
+use sfml::graphics::Color;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["label", "temp", "crit"];
+
+/// Temperatures of sensors in `/sys/class/hwmon` and `/sys/class/thermal`.
+/// Each sensor is drawn in its own grid cell
+pub struct Temperature {
+    hwmon_path: PathBuf,
+    thermal_path: PathBuf,
+    /// Labels of sensors to show, or only the hottest sensor if empty
+    sensors: Vec<Pattern>,
+    format: String,
+    interval: Duration,
+    trigger_show: bool,
+    /// Temperature to draw in `warning_color` at
+    warning_temp: Option<f64>,
+    warning_color: Color,
+    /// Temperature to highlight and show the panel at, overriding the
+    /// sensor's critical temperature
+    critical_temp: Option<f64>,
+    /// Labels of sensors that were critical on the last pull
+    last_critical: Mutex<Vec<String>>,
+    texts: Mutex<Vec<Markup>>,
+    text_config: TextConfig,
+}
+
+/// A temperature reading, in degrees Celsius
+struct Sensor {
+    label: String,
+    temp: f64,
+    /// Critical temperature reported by the sensor
+    crit: Option<f64>,
+}
+
+impl Temperature {
+    /// Read sensors in both hwmon and thermal zones. Errors are logged and
+    /// skipped, as some sensors can't be read while their device is asleep
+    fn read_sensors(&self) -> Vec<Sensor> {
+        let mut sensors = vec![];
+        for hwmon_path in sorted_dir(&self.hwmon_path) {
+            if let Err(e) = read_hwmon(&hwmon_path, &mut sensors) {
+                debug!("Failed to read {}: {}", hwmon_path.display(), e);
+            }
+        }
+        for zone_path in sorted_dir(&self.thermal_path) {
+            let zone_name = zone_path.file_name().unwrap_or_default();
+            if !zone_name.to_string_lossy().starts_with("thermal_zone") {
+                continue;
+            }
+            match read_thermal_zone(&zone_path) {
+                Ok(sensor) => sensors.push(sensor),
+                Err(e) => {
+                    debug!("Failed to read {}: {}", zone_path.display(), e)
+                }
+            }
+        }
+        sensors
+    }
+
+    /// Select the configured sensors
+    fn select(&self, sensors: Vec<Sensor>) -> Vec<Sensor> {
+        if self.sensors.is_empty() {
+            return sensors
+                .into_iter()
+                .max_by(|a, b| {
+                    a.temp.partial_cmp(&b.temp).unwrap_or(Ordering::Equal)
+                })
+                .into_iter()
+                .collect();
+        }
+        sensors
+            .into_iter()
+            .filter(|sensor| {
+                self.sensors
+                    .iter()
+                    .any(|pattern| pattern.matches(&sensor.label))
+            })
+            .collect()
+    }
+
+    fn critical_temp(&self, sensor: &Sensor) -> Option<f64> {
+        self.critical_temp.or(sensor.crit)
+    }
+
+    fn is_critical(&self, sensor: &Sensor) -> bool {
+        matches!(self.critical_temp(sensor), Some(crit) if sensor.temp >= crit)
+    }
+
+    fn is_warning(&self, sensor: &Sensor) -> bool {
+        matches!(self.warning_temp, Some(warning) if sensor.temp >= warning)
+    }
+
+    fn format_sensor(&self, sensor: &Sensor) -> Markup {
+        let mut values = HashMap::new();
+        values.insert("label", sensor.label.clone());
+        values.insert("temp", format!("{:.0}", sensor.temp));
+        values.insert(
+            "crit",
+            self.critical_temp(sensor)
+                .map(|crit| format!("{:.0}", crit))
+                .unwrap_or_default(),
+        );
+        let markup =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+
+        if self.is_critical(sensor) {
+            markup.urgent()
+        } else if self.is_warning(sensor) {
+            markup.wrap(Tag::Color(self.warning_color))
+        } else {
+            markup
+        }
+    }
+}
+
+impl PulledItem for Temperature {
+    fn pull(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let sensors = self.select(self.read_sensors());
+        if sensors.is_empty() {
+            warn!("No temperature sensors found");
+            *self.texts.lock().unwrap() =
+                vec![Markup::error("No temperature sensors")];
+            return Ok(());
+        }
+        trace!("Updating temperature: {} sensors", sensors.len());
+        *self.texts.lock().unwrap() =
+            sensors.iter().map(|s| self.format_sensor(s)).collect();
+
+        // Sensors are compared by label with the last pull, so that a sensor
+        // is announced when it crosses the critical temperature
+        let critical = sensors
+            .iter()
+            .filter(|sensor| self.is_critical(sensor))
+            .map(|sensor| sensor.label.clone())
+            .collect::<Vec<_>>();
+        let mut last_critical = self.last_critical.lock().unwrap();
+        let became_critical =
+            critical.iter().any(|label| !last_critical.contains(label));
+        *last_critical = critical;
+        if self.trigger_show || became_critical {
+            window_command_channel.send(window::Command::Show).unwrap();
+        }
+        Ok(())
+    }
+
+    fn get_interval(&self) -> Duration { self.interval }
+}
+
+impl TextItem for Temperature {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok(text_item::first_text(self.get_texts()?, &self.text_config))
+    }
+
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        Ok(text_item::cell_texts(&self.texts, &self.text_config))
+    }
+}
+
+impl Item for Temperature {}
+
+impl ItemFromConfig for Temperature {
+    fn name() -> &'static str { "temperature" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(
+            hwmon_path,
+            config,
+            into_string,
+            "/sys/class/hwmon".into()
+        );
+        config_get!(
+            thermal_path,
+            config,
+            into_string,
+            "/sys/class/thermal".into()
+        );
+        config_get!(sensors, config, into_string, list);
+        config_get!(format, config, into_string, "{temp}°C".into());
+        config_get!(interval_sec, config, as_f64, 5.0);
+        config_get!(trigger_show, config, as_bool, false);
+        config_get!(warning_temp, config, as_i64);
+        config_get!(warning_color, config, into_string, "ffb000".into());
+        config_get!(critical_temp, config, as_i64);
+        util::check_template(&format, FORMAT_KEYS)?;
+
+        let sensors = sensors
+            .iter()
+            .map(|sensor: &String| {
+                Pattern::new(sensor).chain_err(|| {
+                    format!("Invalid sensor pattern: {}", sensor)
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Box::new(Temperature {
+            hwmon_path: hwmon_path.into(),
+            thermal_path: thermal_path.into(),
+            sensors,
+            format,
+            interval: util::duration_from_config(
+                "interval-sec",
+                interval_sec,
+            )?,
+            trigger_show,
+            warning_temp: warning_temp.map(|temp| temp as f64),
+            warning_color: util::hex_color(&warning_color)?,
+            critical_temp: critical_temp.map(|temp| temp as f64),
+            last_critical: Mutex::new(vec![]),
+            texts: Mutex::new(vec![]),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Read the `tempN_*` sensors of a hwmon device. Sensors without a label are
+/// labelled with the device's name
+fn read_hwmon(hwmon_path: &Path, sensors: &mut Vec<Sensor>) -> Result<()> {
+    let name = util::read_trimmed(hwmon_path.join("name"))?;
+    let read_millidegrees = |file: String| -> Option<f64> {
+        let value = util::read_trimmed(hwmon_path.join(file)).ok()?;
+        value.parse::<f64>().ok().map(|value| value / 1000.0)
+    };
+
+    for path in sorted_dir(hwmon_path) {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let prefix = match file_name.strip_suffix("_input") {
+            Some(prefix) if prefix.starts_with("temp") => prefix,
+            _ => continue,
+        };
+        let temp = match read_millidegrees(file_name.clone()) {
+            Some(temp) => temp,
+            None => continue,
+        };
+        let label = util::read_trimmed(hwmon_path.join(format!(
+            "{}_label",
+            prefix
+        )))
+        .unwrap_or_else(|_| name.clone());
+        sensors.push(Sensor {
+            label,
+            temp,
+            crit: read_millidegrees(format!("{}_crit", prefix)),
+        });
+    }
+    Ok(())
+}
+
+/// Read a thermal zone, labelled with its type
+fn read_thermal_zone(zone_path: &Path) -> Result<Sensor> {
+    let read = |file: &str| util::read_trimmed(zone_path.join(file));
+    let to_degrees = |millidegrees: String| -> Result<f64> {
+        let millidegrees = millidegrees.parse::<f64>().chain_err(|| {
+            format!("Invalid temperature in {}", zone_path.display())
+        })?;
+        Ok(millidegrees / 1000.0)
+    };
+
+    // Trip points are numbered from zero, and have a type such as `passive`
+    // or `critical`
+    let mut crit = None;
+    for index in 0.. {
+        let trip_type = match read(&format!("trip_point_{}_type", index)) {
+            Ok(trip_type) => trip_type,
+            Err(_) => break,
+        };
+        if trip_type == "critical" {
+            crit = Some(to_degrees(read(&format!(
+                "trip_point_{}_temp",
+                index
+            ))?)?);
+            break;
+        }
+    }
+
+    Ok(Sensor {
+        label: read("type")?,
+        temp: to_degrees(read("temp")?)?,
+        crit,
+    })
+}
+
+/// Get the paths in a directory sorted by name, or nothing if it can't be
+/// read
+fn sorted_dir(path: &Path) -> Vec<PathBuf> {
+    let mut paths = fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
@@ -1,56 +1,223 @@
 use crate::config::Config;
 use crate::error::*;
-use crate::item::ItemDraw;
+use crate::item::{Content, ItemDraw, Layout, Markup, Resources, Style};
 use crate::util;
-use crate::window::{DrawConfig, DrawableConfig, Window};
+use crate::window::{DrawConfig, Window};
 
-use sfml::graphics::{Color, Text};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Mutex;
 
-const FONT_SIZE_SCALE: f32 = 1.40;
+use sfml::graphics::{Color, Text, Texture};
+
+/// Speed that overflowing text scrolls at, in pixels per second
+const SCROLL_SPEED: f32 = 40.0;
+/// Gap between the end and start of scrolling text, in pixels
+const SCROLL_GAP: f32 = 40.0;
 
 /// Item that draws text
 pub trait TextItem: Send + Sync {
     /// Get text to be drawn
-    fn get_text(&self) -> Result<(String, TextConfig)>;
+    fn get_text(&self) -> Result<(Markup, TextConfig)>;
+
+    /// Get texts to be drawn, each in its own grid cell
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        Ok(vec![self.get_text()?])
+    }
 }
 
 impl<T: TextItem> ItemDraw for T {
     fn draw(&self, window: &mut Window) -> Result<()> {
-        let (text, text_config) = self.get_text()?;
-        let font = window.config.font.clone();
-        let mut sfml_text: Text =
-            Text::new(&text, &font, window.config.font_size);
-        sfml_text.set_fill_color(&text_config.color);
-        sfml_text.set_outline_color(&Color::rgb(10, 10, 10));
-        sfml_text.set_outline_thickness(1.0);
-        let bounds = sfml_text.global_bounds();
-
-        trace!("Drawing text: \"{}\"", text);
-        window.draw(
-            vec![DrawableConfig::new(&sfml_text)],
-            text_config.draw_config,
-            (bounds.left + bounds.width) as u32,
-            (window.config.font_size as f32 * FONT_SIZE_SCALE) as u32,
-        );
-
+        for (markup, text_config) in self.get_texts()? {
+            draw_text(window, markup, text_config);
+        }
         Ok(())
     }
 }
 
+/// Get the first of an item's texts, or empty text if it has none, for items
+/// that draw a grid cell per text
+pub fn first_text(
+    texts: Vec<(Markup, TextConfig)>,
+    text_config: &TextConfig,
+) -> (Markup, TextConfig)
+{
+    texts
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| (Markup::default(), text_config.clone()))
+}
+
+/// Pair each of the texts in a list with the same text config
+pub fn cell_texts(
+    texts: &Mutex<Vec<Markup>>,
+    text_config: &TextConfig,
+) -> Vec<(Markup, TextConfig)>
+{
+    texts
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|markup| (markup.clone(), text_config.clone()))
+        .collect()
+}
+
+/// Draw text in its own grid cell
+fn draw_text(window: &mut Window, markup: Markup, text_config: TextConfig) {
+    let font = window.config.font.clone();
+    let icon_font = window.config.icon_font.clone();
+    let icons = window.config.icons.clone();
+    let runs = markup.runs(Style {
+        color: text_config.color,
+        bold: false,
+        italic: false,
+        size: window.config.font_size,
+    });
+
+    // Load the textures before laying out, so that sprites can borrow them
+    let mut textures: HashMap<String, Option<Rc<Texture>>> = HashMap::new();
+    for run in &runs {
+        if let Content::Image(src) = &run.content {
+            textures.insert(src.clone(), window.texture_cache.get(src));
+        }
+    }
+
+    let padding = (text_config.draw_config.horizontal_padding * 2) as f32;
+    let max_width = text_config
+        .max_width_cells
+        .map(|cells| (cells * window.config.grid_size) as f32 - padding);
+    let mut layout = Layout::new(
+        &runs,
+        &Resources {
+            font: &font,
+            icon_font: icon_font.as_ref().map(|f| f.as_ref()),
+            icons: &icons,
+            textures: &textures,
+        },
+        max_width,
+    );
+
+    // Handle text that is wider than the space available in the grid
+    let available_width = max_width.unwrap_or(
+        (window.config.grid_width * window.config.grid_size) as f32 - padding,
+    );
+    let drawable_configs = match text_config.overflow {
+        Overflow::Ellipsis => {
+            layout.ellipsize(available_width);
+            layout.drawable_configs(0.0)
+        }
+        Overflow::Scroll if layout.width() > available_width => {
+            // Draw the text twice, so that the start of the text follows the
+            // end when looping
+            let period = layout.width() + SCROLL_GAP;
+            let offset = (window.visible_duration().as_secs_f32()
+                * SCROLL_SPEED)
+                % period;
+            let mut drawable_configs = layout.drawable_configs(-offset);
+            drawable_configs
+                .append(&mut layout.drawable_configs(period - offset));
+            drawable_configs
+        }
+        Overflow::Clip | Overflow::Scroll => layout.drawable_configs(0.0),
+    };
+
+    let min_width = match &text_config.min_width {
+        Some(MinWidth::Pixels(pixels)) => *pixels as f32,
+        Some(MinWidth::Text(text)) => {
+            Text::new(text, &font, window.config.font_size)
+                .local_bounds()
+                .width
+        }
+        None => 0.0,
+    };
+
+    trace!("Drawing {} runs", runs.len());
+    window.draw(
+        drawable_configs,
+        text_config.draw_config,
+        layout.width().max(min_width).min(available_width).ceil() as u32,
+        layout.height().ceil() as u32,
+    );
+}
+
 /// Dictate how to draw text
 #[derive(Clone)]
 pub struct TextConfig {
     draw_config: DrawConfig,
     color: Color,
+    /// Wrap text that is wider than this many grid cells
+    max_width_cells: Option<u32>,
+    overflow: Overflow,
+    min_width: Option<MinWidth>,
 }
 
 impl TextConfig {
     #[allow(missing_docs)]
     pub fn parse(config: &mut Config) -> Result<Self> {
         config_get!(color, config, into_string, "ffffff".into());
+        config_get!(max_width_cells, config, as_i64);
+        config_get!(overflow, config, into_string, "clip".into());
+        config_get!(min_width, config, as_i64);
+        if let Some(max_width_cells) = max_width_cells {
+            ensure!(
+                max_width_cells >= 1,
+                ErrorKind::ConfigError(
+                    "'max-width-cells' must be at least 1".into()
+                )
+            );
+        }
         Ok(TextConfig {
             draw_config: DrawConfig::parse(config)?,
             color: util::hex_color(&color)?,
+            max_width_cells: max_width_cells.map(|cells| cells as u32),
+            overflow: overflow.parse()?,
+            min_width: min_width.map(|pixels| MinWidth::Pixels(pixels as u32)),
+        })
+    }
+
+    /// Copy the config, overriding the minimum width if one is given
+    pub fn with_min_width(&self, min_width: Option<MinWidth>) -> Self {
+        TextConfig {
+            min_width: min_width.or_else(|| self.min_width.clone()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Minimum width of an item
+#[derive(Clone)]
+pub enum MinWidth {
+    #[allow(missing_docs)]
+    Pixels(u32),
+    /// The width of the text
+    Text(String),
+}
+
+/// How to draw text that is wider than the space available
+#[derive(Clone, Copy)]
+pub enum Overflow {
+    /// Cut off the text at the edge of the item
+    Clip,
+    /// Truncate the text, ending it with an ellipsis
+    Ellipsis,
+    /// Scroll the text horizontally while the panel is visible
+    Scroll,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "clip" => Overflow::Clip,
+            "ellipsis" => Overflow::Ellipsis,
+            "scroll" => Overflow::Scroll,
+            s => {
+                return Err(ErrorKind::ConfigError(format!(
+                    "Unrecognized overflow: {}",
+                    s
+                ))
+                .into());
+            }
         })
     }
 }
@@ -0,0 +1,451 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, TextConfig, TextItem,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["name", "time", "state", "phase", "cycle"];
+
+/// How often to show the panel while waiting for an expired timer to be
+/// acknowledged, so that it stays visible
+const REMIND_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Timer controlled with IPC commands such as `timer work start`. Countdowns
+/// show the panel when they expire, and keep it shown until they're
+/// acknowledged with a click or the `acknowledge` command
+pub struct Timer {
+    /// Name used in commands to refer to the timer
+    timer_name: String,
+    mode: Mode,
+    format: String,
+    state: Mutex<State>,
+    /// Wakes the item's thread when the timer is changed by a command
+    wake: Mutex<Option<mpsc::Sender<()>>>,
+    text_config: TextConfig,
+}
+
+enum Mode {
+    Countdown(Duration),
+    Stopwatch,
+    /// Alternates between work and breaks, where every `cycles` work periods
+    /// are followed by a long break
+    Pomodoro {
+        work: Duration,
+        short_break: Duration,
+        long_break: Duration,
+        cycles: usize,
+    },
+}
+
+#[derive(Default)]
+struct State {
+    /// Time counted before the timer was last started
+    counted: Duration,
+    /// When the timer was last started, or `None` if it isn't running
+    started: Option<Instant>,
+    /// Whether the countdown has expired and not been acknowledged
+    expired: bool,
+    /// Index of the pomodoro phase, where work phases are even
+    phase: usize,
+}
+
+impl State {
+    fn elapsed(&self) -> Duration {
+        self.counted
+            + self
+                .started
+                .map(|started| started.elapsed())
+                .unwrap_or_default()
+    }
+
+    fn start(&mut self) {
+        if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    fn pause(&mut self) {
+        self.counted = self.elapsed();
+        self.started = None;
+    }
+}
+
+impl Timer {
+    /// Length of the current phase, or `None` for stopwatches
+    fn duration(&self, phase: usize) -> Option<Duration> {
+        match self.mode {
+            Mode::Countdown(duration) => Some(duration),
+            Mode::Stopwatch => None,
+            Mode::Pomodoro {
+                work,
+                short_break,
+                long_break,
+                cycles,
+            } => Some(match phase_name(phase, cycles) {
+                "work" => work,
+                "long break" => long_break,
+                _ => short_break,
+            }),
+        }
+    }
+
+    /// Time left before the countdown expires
+    fn remaining(&self, state: &State) -> Option<Duration> {
+        self.duration(state.phase)
+            .map(|duration| duration.saturating_sub(state.elapsed()))
+    }
+
+    /// Expire the countdown if it's finished, returning whether it's expired
+    fn check_expired(&self, state: &mut State) -> bool {
+        if state.started.is_some()
+            && self.remaining(state) == Some(Duration::default())
+        {
+            debug!("Timer {} expired", self.timer_name);
+            *state = State {
+                expired: true,
+                // Pomodoros move on to the next phase, which starts when the
+                // expiry is acknowledged
+                phase: match self.mode {
+                    Mode::Pomodoro { .. } => state.phase + 1,
+                    _ => state.phase,
+                },
+                ..State::default()
+            };
+        }
+        state.expired
+    }
+
+    fn acknowledge(&self, state: &mut State) {
+        if !state.expired {
+            return;
+        }
+        state.expired = false;
+        if let Mode::Pomodoro { .. } = self.mode {
+            state.start();
+        }
+    }
+
+    /// Apply an action to the timer
+    fn action(&self, action: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match action {
+            "start" => {
+                self.acknowledge(&mut state);
+                state.start();
+            }
+            "pause" => state.pause(),
+            "toggle" if state.expired => self.acknowledge(&mut state),
+            "toggle" if state.started.is_some() => state.pause(),
+            "toggle" => state.start(),
+            "reset" => *state = State::default(),
+            "acknowledge" => self.acknowledge(&mut state),
+            action => bail!(ErrorKind::ConfigError(format!(
+                "Unrecognised timer action: {}",
+                action
+            ))),
+        }
+        drop(state);
+
+        if let Some(wake) = self.wake.lock().unwrap().as_ref() {
+            wake.send(()).unwrap();
+        }
+        Ok(())
+    }
+}
+
+impl ItemStart for Timer {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let (wake_send, wake_receive) = mpsc::channel();
+        *self.wake.lock().unwrap() = Some(wake_send);
+
+        // Sleep until the countdown expires or a command changes the timer,
+        // and keep showing the panel while it's expired
+        loop {
+            let timeout = {
+                let mut state = self.state.lock().unwrap();
+                if self.check_expired(&mut state) {
+                    window_command_channel.send(window::Command::Show).unwrap();
+                    Some(REMIND_INTERVAL)
+                } else if state.started.is_some() {
+                    self.remaining(&state)
+                } else {
+                    None
+                }
+            };
+            // The item keeps the sender alive, so this only returns on a wake
+            // or timeout
+            let _ = match timeout {
+                Some(timeout) => wake_receive.recv_timeout(timeout),
+                None => wake_receive.recv().map_err(Into::into),
+            };
+        }
+    }
+}
+
+impl TextItem for Timer {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        let state = self.state.lock().unwrap();
+        let time = self.remaining(&state).unwrap_or_else(|| state.elapsed());
+        let seconds = time.as_secs();
+
+        let mut values = HashMap::new();
+        values.insert("name", self.timer_name.clone());
+        values.insert(
+            "time",
+            if seconds >= 3600 {
+                format!(
+                    "{}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )
+            } else {
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            },
+        );
+        values.insert(
+            "state",
+            if state.expired {
+                "expired"
+            } else if state.started.is_some() {
+                "running"
+            } else if state.counted > Duration::default() {
+                "paused"
+            } else {
+                "stopped"
+            }
+            .into(),
+        );
+        let (phase, cycle) = match self.mode {
+            Mode::Pomodoro { cycles, .. } => (
+                phase_name(state.phase, cycles).into(),
+                (state.phase / 2 % cycles + 1).to_string(),
+            ),
+            _ => (String::new(), String::new()),
+        };
+        values.insert("phase", phase);
+        values.insert("cycle", cycle);
+
+        let markup =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+        let markup = if state.expired { markup.urgent() } else { markup };
+        Ok((markup, self.text_config.clone()))
+    }
+}
+
+impl Item for Timer {
+    /// Acknowledge the timer if it's expired, otherwise start or pause it
+    fn click(&self, _click: &window::Click) -> Result<()> {
+        self.action("toggle")
+    }
+
+    /// Handle commands of the form `timer <name> <action>`
+    fn command(&self, words: &[String]) -> Result<bool> {
+        match words {
+            [item, name, action]
+                if item == Self::name() && *name == self.timer_name =>
+            {
+                debug!("Timer {} received action {}", name, action);
+                self.action(action)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+impl ItemFromConfig for Timer {
+    fn name() -> &'static str { "timer" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(timer_name, config, into_string, required);
+        config_get!(mode, config, into_string, "countdown".into());
+        config_get!(duration_sec, config, as_f64, 300.0);
+        config_get!(work_sec, config, as_f64, 1500.0);
+        config_get!(break_sec, config, as_f64, 300.0);
+        config_get!(long_break_sec, config, as_f64, 900.0);
+        config_get!(cycles, config, as_i64, 4);
+        config_get!(format, config, into_string, "{name}: {time}".into());
+        util::check_template(&format, FORMAT_KEYS)?;
+
+        let mode = match mode.as_str() {
+            "countdown" => Mode::Countdown(util::duration_from_config(
+                "duration-sec",
+                duration_sec,
+            )?),
+            "stopwatch" => Mode::Stopwatch,
+            "pomodoro" => {
+                ensure!(
+                    cycles >= 1,
+                    ErrorKind::ConfigError(
+                        "'cycles' must be at least 1".into()
+                    )
+                );
+                Mode::Pomodoro {
+                    work: util::duration_from_config("work-sec", work_sec)?,
+                    short_break: util::duration_from_config(
+                        "break-sec",
+                        break_sec,
+                    )?,
+                    long_break: util::duration_from_config(
+                        "long-break-sec",
+                        long_break_sec,
+                    )?,
+                    cycles: cycles as usize,
+                }
+            }
+            mode => bail!(ErrorKind::ConfigError(format!(
+                "Unrecognised timer mode: {}",
+                mode
+            ))),
+        };
+
+        Ok(Box::new(Timer {
+            timer_name,
+            mode,
+            format,
+            state: Mutex::new(State::default()),
+            wake: Mutex::new(None),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Name of a pomodoro phase, where work phases are even and every `cycles`
+/// work phases are followed by a long break
+fn phase_name(phase: usize, cycles: usize) -> &'static str {
+    if phase.is_multiple_of(2) {
+        "work"
+    } else if (phase / 2 + 1).is_multiple_of(cycles) {
+        "long break"
+    } else {
+        "break"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(mode: Mode) -> Timer {
+        Timer {
+            timer_name: "test".into(),
+            mode,
+            format: "{time}".into(),
+            state: Mutex::new(State::default()),
+            wake: Mutex::new(None),
+            text_config: TextConfig::parse(&mut Config::new()).unwrap(),
+        }
+    }
+
+    fn pomodoro() -> Timer {
+        timer(Mode::Pomodoro {
+            work: Duration::from_secs(60),
+            short_break: Duration::from_secs(10),
+            long_break: Duration::from_secs(30),
+            cycles: 2,
+        })
+    }
+
+    /// A running state that started `seconds` ago
+    fn started(seconds: u64) -> State {
+        State {
+            started: Some(Instant::now() - Duration::from_secs(seconds)),
+            ..State::default()
+        }
+    }
+
+    #[test]
+    fn phase_name_follows_cycles() {
+        let names: Vec<_> = (0..8).map(|phase| phase_name(phase, 2)).collect();
+        assert_eq!(names, [
+            "work",
+            "break",
+            "work",
+            "long break",
+            "work",
+            "break",
+            "work",
+            "long break",
+        ]);
+        assert_eq!(phase_name(1, 1), "long break");
+    }
+
+    #[test]
+    fn check_expired_waits_for_countdown() {
+        let timer = timer(Mode::Countdown(Duration::from_secs(60)));
+        let mut state = started(30);
+        assert!(!timer.check_expired(&mut state));
+        assert!(state.started.is_some());
+
+        let mut state = started(60);
+        assert!(timer.check_expired(&mut state));
+        assert!(state.started.is_none());
+        assert_eq!(state.phase, 0);
+    }
+
+    #[test]
+    fn check_expired_ignores_stopwatches_and_stopped_timers() {
+        let timer = timer(Mode::Countdown(Duration::from_secs(60)));
+        let mut state = State {
+            counted: Duration::from_secs(120),
+            ..State::default()
+        };
+        assert!(!timer.check_expired(&mut state));
+
+        let stopwatch = self::timer(Mode::Stopwatch);
+        assert!(!stopwatch.check_expired(&mut started(3600)));
+    }
+
+    #[test]
+    fn check_expired_moves_pomodoro_to_next_phase() {
+        let timer = pomodoro();
+        let mut state = started(60);
+        assert!(timer.check_expired(&mut state));
+        assert_eq!(state.phase, 1);
+        // Breaks are shorter than work
+        let mut state = State { phase: 1, ..started(10) };
+        assert!(timer.check_expired(&mut state));
+        assert_eq!(state.phase, 2);
+    }
+
+    #[test]
+    fn acknowledge_starts_next_pomodoro_phase() {
+        let timer = pomodoro();
+        let mut state = started(60);
+        timer.check_expired(&mut state);
+        timer.acknowledge(&mut state);
+        assert!(!state.expired);
+        assert!(state.started.is_some());
+        assert_eq!(state.phase, 1);
+    }
+
+    #[test]
+    fn acknowledge_leaves_countdown_stopped() {
+        let timer = timer(Mode::Countdown(Duration::from_secs(60)));
+        let mut state = started(60);
+        timer.check_expired(&mut state);
+        timer.acknowledge(&mut state);
+        assert!(!state.expired);
+        assert!(state.started.is_none());
+    }
+
+    #[test]
+    fn acknowledge_ignores_timers_that_have_not_expired() {
+        let timer = pomodoro();
+        let mut state = State::default();
+        timer.acknowledge(&mut state);
+        assert!(state.started.is_none());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::config::Config;
+use crate::error::*;
+use crate::item::i3_ipc;
+use crate::item::text_item;
+use crate::item::{
+    Item, ItemFromConfig, ItemStart, Markup, Tag, TextConfig, TextItem,
+    RETRY_INTERVAL,
+};
+use crate::util;
+use crate::window;
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use serde_json::Value;
+use sfml::graphics::Color;
+
+/// Keys that can be used in the format
+const FORMAT_KEYS: &[&str] = &["name", "num", "output"];
+
+/// Keys that can be used in the mode format
+const MODE_FORMAT_KEYS: &[&str] = &["mode"];
+
+/// Name of the mode that i3 and sway are in when no binding mode is active
+const DEFAULT_MODE: &str = "default";
+
+/// Workspaces and binding mode of i3 or sway, received over their IPC
+/// socket. Each workspace is drawn in its own grid cell, and clicking one
+/// switches to it
+pub struct Workspaces {
+    /// Path of the IPC socket, or found from the environment if `None`
+    socket_path: Option<String>,
+    /// Only show workspaces on this output
+    output: Option<String>,
+    format: String,
+    mode_format: String,
+    focused_color: Color,
+    /// Whether to show the panel when switching workspace or entering a
+    /// binding mode
+    trigger_show: bool,
+    /// Sends commands to the thread that runs them, so that clicks don't
+    /// wait for the window manager
+    commands: Mutex<Option<mpsc::Sender<String>>>,
+    state: Mutex<State>,
+    text_config: TextConfig,
+}
+
+#[derive(Default)]
+struct State {
+    connected: bool,
+    workspaces: Vec<Workspace>,
+    mode: String,
+}
+
+struct Workspace {
+    name: String,
+    num: Option<i64>,
+    output: String,
+    focused: bool,
+    urgent: bool,
+}
+
+impl Workspaces {
+    /// Follow the window manager's events until the connection fails
+    fn follow(
+        &self,
+        window_command_channel: &mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let socket_path = self.socket_path.as_deref();
+        let mut events = i3_ipc::Connection::open(socket_path)?;
+        events.send(i3_ipc::SUBSCRIBE, r#"["workspace", "mode"]"#)?;
+        let (_, reply) = events.receive()?;
+        ensure!(
+            reply.get("success").and_then(Value::as_bool) == Some(true),
+            ErrorKind::CommandError("Failed to subscribe to IPC events".into())
+        );
+
+        let mut requests = i3_ipc::Connection::open(socket_path)?;
+        let mode = requests
+            .request(i3_ipc::GET_BINDING_STATE, "")
+            .ok()
+            .and_then(|state| {
+                state.get("name").and_then(Value::as_str).map(String::from)
+            })
+            .unwrap_or_else(|| DEFAULT_MODE.into());
+        {
+            let mut state = self.state.lock().unwrap();
+            state.connected = true;
+            state.mode = mode;
+        }
+        self.update_workspaces(&mut requests)?;
+        info!("Connected to window manager IPC");
+
+        loop {
+            let (event_type, event) = events.receive()?;
+            let change = event
+                .get("change")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let show = match event_type {
+                i3_ipc::WORKSPACE_EVENT => {
+                    trace!("Workspace event: {}", change);
+                    self.update_workspaces(&mut requests)?;
+                    change == "focus" || change == "urgent"
+                }
+                // The change of mode events is the name of the mode
+                i3_ipc::MODE_EVENT => {
+                    trace!("Mode event: {}", change);
+                    self.state.lock().unwrap().mode = change.to_string();
+                    change != DEFAULT_MODE
+                }
+                _ => false,
+            };
+            if show && self.trigger_show {
+                window_command_channel.send(window::Command::Show).unwrap();
+            }
+        }
+    }
+
+    fn update_workspaces(
+        &self,
+        requests: &mut i3_ipc::Connection,
+    ) -> Result<()>
+    {
+        let reply = requests.request(i3_ipc::GET_WORKSPACES, "")?;
+        let workspaces = reply
+            .as_array()
+            .chain_err(|| "Workspaces reply must be an array")?
+            .iter()
+            .map(|workspace| {
+                let get_bool = |key: &str| {
+                    workspace.get(key).and_then(Value::as_bool) == Some(true)
+                };
+                let get_str = |key: &str| {
+                    workspace
+                        .get(key)
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                Workspace {
+                    name: get_str("name"),
+                    // Named workspaces have a number of -1
+                    num: workspace
+                        .get("num")
+                        .and_then(Value::as_i64)
+                        .filter(|num| *num >= 0),
+                    output: get_str("output"),
+                    focused: get_bool("focused"),
+                    urgent: get_bool("urgent"),
+                }
+            })
+            .filter(|workspace| match &self.output {
+                Some(output) => &workspace.output == output,
+                None => true,
+            })
+            .collect();
+        self.state.lock().unwrap().workspaces = workspaces;
+        Ok(())
+    }
+
+    fn format_workspace(&self, workspace: &Workspace) -> Markup {
+        let mut values = HashMap::new();
+        values.insert("name", workspace.name.clone());
+        values.insert(
+            "num",
+            workspace.num.map(|num| num.to_string()).unwrap_or_default(),
+        );
+        values.insert("output", workspace.output.clone());
+        let markup =
+            Markup::parse_or_plain(&util::fill_template(&self.format, &values));
+
+        if workspace.urgent {
+            markup.urgent()
+        } else if workspace.focused {
+            markup.wrap(Tag::Bold).wrap(Tag::Color(self.focused_color))
+        } else {
+            markup
+        }
+    }
+
+    fn format_mode(&self, mode: &str) -> Markup {
+        let mut values = HashMap::new();
+        values.insert("mode", mode.to_string());
+        Markup::parse_or_plain(&util::fill_template(&self.mode_format, &values))
+    }
+}
+
+impl ItemStart for Workspaces {
+    fn start(
+        &self,
+        window_command_channel: mpsc::Sender<window::Command>,
+    ) -> Result<()>
+    {
+        let (command_send, command_receive) = mpsc::channel();
+        *self.commands.lock().unwrap() = Some(command_send);
+        let socket_path = self.socket_path.clone();
+        util::start_thread(move || run_commands(socket_path, command_receive));
+
+        loop {
+            if let Err(e) = self.follow(&window_command_channel) {
+                warn!("Lost connection to window manager IPC: {}", e);
+            }
+            self.state.lock().unwrap().connected = false;
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+}
+
+impl TextItem for Workspaces {
+    fn get_text(&self) -> Result<(Markup, TextConfig)> {
+        Ok(text_item::first_text(self.get_texts()?, &self.text_config))
+    }
+
+    /// Get the text of the binding mode if one is active, followed by each
+    /// workspace
+    fn get_texts(&self) -> Result<Vec<(Markup, TextConfig)>> {
+        let state = self.state.lock().unwrap();
+        if !state.connected {
+            return Ok(vec![(
+                Markup::error("No window manager IPC"),
+                self.text_config.clone(),
+            )]);
+        }
+        let mode = Some(&state.mode)
+            .filter(|mode| *mode != DEFAULT_MODE)
+            .map(|mode| self.format_mode(mode));
+        Ok(mode
+            .into_iter()
+            .chain(state.workspaces.iter().map(|w| self.format_workspace(w)))
+            .map(|markup| (markup, self.text_config.clone()))
+            .collect())
+    }
+}
+
+impl Item for Workspaces {
+    /// Switch to the clicked workspace
+    fn click(&self, click: &window::Click) -> Result<()> {
+        let name = {
+            let state = self.state.lock().unwrap();
+            let mode_cells = if state.mode != DEFAULT_MODE { 1 } else { 0 };
+            match click
+                .cell
+                .checked_sub(mode_cells)
+                .and_then(|index| state.workspaces.get(index))
+            {
+                Some(workspace) => workspace.name.clone(),
+                None => return Ok(()),
+            }
+        };
+        debug!("Switching to workspace {}", name);
+        let command =
+            format!("workspace \"{}\"", name.replace('"', "\\\""));
+        if let Some(commands) = self.commands.lock().unwrap().as_ref() {
+            commands.send(command).unwrap();
+        }
+        Ok(())
+    }
+}
+
+impl ItemFromConfig for Workspaces {
+    fn name() -> &'static str { "workspaces" }
+
+    fn parse(config: &mut Config) -> Result<Box<dyn Item>> {
+        config_get!(socket_path, config, into_string);
+        config_get!(output, config, into_string);
+        config_get!(format, config, into_string, "{name}".into());
+        config_get!(mode_format, config, into_string, "<b>{mode}</b>".into());
+        config_get!(focused_color, config, into_string, "88c0ff".into());
+        config_get!(trigger_show, config, as_bool, true);
+        util::check_template(&format, FORMAT_KEYS)?;
+        util::check_template(&mode_format, MODE_FORMAT_KEYS)?;
+
+        Ok(Box::new(Workspaces {
+            socket_path,
+            output,
+            format,
+            mode_format,
+            focused_color: util::hex_color(&focused_color)?,
+            trigger_show,
+            commands: Mutex::new(None),
+            state: Mutex::new(State::default()),
+            text_config: TextConfig::parse(config)?,
+        }))
+    }
+}
+
+/// Run commands on a connection of their own, which is opened when a command
+/// is received so that it recovers from the window manager restarting
+fn run_commands(
+    socket_path: Option<String>,
+    commands: mpsc::Receiver<String>,
+) -> Result<()>
+{
+    let mut connection = None;
+    for command in commands {
+        let result = match connection.take() {
+            Some(connection) => Ok(connection),
+            None => i3_ipc::Connection::open(socket_path.as_deref()),
+        }
+        .and_then(|mut connection| {
+            connection.request(i3_ipc::RUN_COMMAND, &command)?;
+            Ok(connection)
+        });
+        match result {
+            Ok(opened) => connection = Some(opened),
+            Err(e) => warn!("Failed to run IPC command {}: {}", command, e),
+        }
+    }
+    Ok(())
+}
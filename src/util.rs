@@ -1,6 +1,12 @@
 //! Utility functions
 
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
 use std::thread;
+use std::time::Duration;
 
 use crate::error::*;
 
@@ -41,3 +47,138 @@ pub fn hex_color(hex: &str) -> Result<Color> {
         ));
     }
 }
+
+/// Get a duration from a config value such as `interval-sec`, which must be a
+/// positive number of seconds
+pub fn duration_from_config(key: &str, seconds: f64) -> Result<Duration> {
+    ensure!(
+        seconds > 0.0 && seconds < u64::MAX as f64,
+        ErrorKind::ConfigError(format!(
+            "'{}' must be a positive number of seconds, got {}",
+            key, seconds
+        ))
+    );
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Read a file, such as a value in `/sys`, with surrounding whitespace trimmed
+pub fn read_trimmed<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    Ok(fs::read_to_string(path)
+        .chain_err(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .into())
+}
+
+/// Format a number of bytes with binary units, e.g. `1.5 GiB`
+pub fn human_bytes(bytes: f64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024.0 {
+        return format!("{:.0} B", bytes);
+    }
+    let mut value = bytes / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+/// Escape text so that it is displayed as-is when parsed as markup
+pub fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+/// Check that a template only uses the given keys
+pub fn check_template(template: &str, keys: &[&str]) -> Result<()> {
+    for key in template_keys(template) {
+        ensure!(
+            keys.contains(&key),
+            ErrorKind::ConfigError(format!(
+                "Unrecognized key {{{}}} in format, expected one of: {}",
+                key,
+                keys.join(", ")
+            ))
+        );
+    }
+    Ok(())
+}
+
+/// Replace `{key}`s in a template with their values, escaped for markup
+pub fn fill_template<K>(template: &str, values: &HashMap<K, String>) -> String
+where K: Borrow<str> + Eq + Hash {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        result.push_str(&rest[..start]);
+        match values.get(&rest[start + 1..end]) {
+            Some(value) => result.push_str(&escape_markup(value)),
+            None => result.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Get the `{key}`s used in a template
+pub fn template_keys(template: &str) -> Vec<&str> {
+    let mut keys = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        keys.push(&rest[start + 1..end]);
+        rest = &rest[end + 1..];
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_template_accepts_known_keys() {
+        assert!(check_template("{a} and {b}", &["a", "b"]).is_ok());
+        assert!(check_template("no keys", &["a"]).is_ok());
+    }
+
+    #[test]
+    fn check_template_rejects_unknown_keys() {
+        assert!(check_template("{a} and {c}", &["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn fill_template_escapes_values() {
+        let mut values = HashMap::new();
+        values.insert("name", "<b>&".to_string());
+        assert_eq!(fill_template("[{name}]", &values), "[&lt;b&gt;&amp;]");
+    }
+
+    #[test]
+    fn fill_template_keeps_unknown_keys_and_unclosed_braces() {
+        let mut values = HashMap::new();
+        values.insert("a", "1".to_string());
+        assert_eq!(fill_template("{a} {b} {a", &values), "1 {b} {a");
+    }
+
+    #[test]
+    fn template_keys_finds_each_key() {
+        assert_eq!(template_keys("{a}-{b}{c"), vec!["a", "b"]);
+    }
+}
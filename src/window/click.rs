@@ -0,0 +1,55 @@
+use sfml::graphics::IntRect;
+use sfml::window::mouse::{Button, Wheel};
+
+/// A mouse click on one of an item's grid cells
+pub struct Click {
+    /// Index of the cell within the item, in the order the item drew them
+    pub cell: usize,
+    /// X11 button number, where 1 is left, 2 is middle, 3 is right, and 4
+    /// to 7 are scrolling up, down, left, and right
+    pub button: u32,
+    /// Horizontal position on the screen
+    pub x: i32,
+    /// Vertical position on the screen
+    pub y: i32,
+    /// Horizontal position relative to the cell
+    pub relative_x: i32,
+    /// Vertical position relative to the cell
+    pub relative_y: i32,
+    /// Width of the cell
+    pub width: i32,
+    /// Height of the cell
+    pub height: i32,
+}
+
+/// A grid cell drawn by an item
+pub struct DrawnCell {
+    /// Index of the item in the window's items
+    pub item: usize,
+    /// Index of the cell within the item
+    pub index: usize,
+    /// Area of the cell in the window
+    pub rect: IntRect,
+}
+
+/// Get the X11 button number of an SFML mouse button
+pub fn button_number(button: Button) -> Option<u32> {
+    match button {
+        Button::Left => Some(1),
+        Button::Middle => Some(2),
+        Button::Right => Some(3),
+        Button::XButton1 => Some(8),
+        Button::XButton2 => Some(9),
+        Button::Count => None,
+    }
+}
+
+/// Get the X11 button number of an SFML mouse wheel scroll
+pub fn wheel_button_number(wheel: Wheel, delta: f32) -> u32 {
+    match (wheel, delta > 0.0) {
+        (Wheel::Vertical, true) => 4,
+        (Wheel::Vertical, false) => 5,
+        (Wheel::Horizontal, true) => 6,
+        (Wheel::Horizontal, false) => 7,
+    }
+}
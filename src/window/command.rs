@@ -6,7 +6,7 @@ use std::str::FromStr;
 use sfml;
 
 /// Commands that can be sent to the window
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum Command {
     /// SFML window event
     Event(sfml::window::Event),
@@ -16,6 +16,9 @@ pub enum Command {
     Hide,
     /// Quit the program
     Quit,
+    /// Command for items, given as words where the first is the name of the
+    /// item, e.g. `timer work start`
+    Item(Vec<String>),
 }
 
 impl FromStr for Command {
@@ -25,6 +28,9 @@ impl FromStr for Command {
             "show" => Ok(Command::Show),
             "hide" => Ok(Command::Hide),
             "quit" => Ok(Command::Quit),
+            s if s.split_whitespace().count() > 1 => Ok(Command::Item(
+                s.split_whitespace().map(String::from).collect(),
+            )),
             s => bail!(ErrorKind::ConfigError(format!(
                 "Unrecognised command: {}",
                 s
@@ -36,10 +42,11 @@ impl FromStr for Command {
 impl fmt::Display for Command {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match self {
-            Command::Show => "show",
-            Command::Hide => "hide",
-            Command::Quit => "quit",
-            Command::Event(_) => "event",
+            Command::Show => "show".into(),
+            Command::Hide => "hide".into(),
+            Command::Quit => "quit".into(),
+            Command::Event(_) => "event".into(),
+            Command::Item(words) => words.join(" "),
         };
         write!(f, "{}", s)
     }
@@ -1,7 +1,9 @@
 use crate::anchor::Anchor;
 use crate::config;
 use crate::error::*;
+use crate::window::icons::icon_names;
 
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::time::Duration;
 
@@ -15,6 +17,8 @@ pub struct Config {
     pub grid_size: u32,
     pub font: Rc<Font>,
     pub font_size: u32,
+    pub icon_font: Option<Rc<Font>>,
+    pub icons: Rc<HashMap<String, char>>,
     pub show_duration: Duration,
     pub anchor: Anchor,
     pub edge_distance: u32,
@@ -29,11 +33,20 @@ impl Config {
         config_get!(show_duration_sec, yaml_object, as_f64, 3.0);
         config_get!(font_path, yaml_object, into_string, required);
         config_get!(font_size, yaml_object, as_i64, 18);
+        config_get!(icon_font_path, yaml_object, into_string);
+        config_get!(icon_names_path, yaml_object, into_string);
         config_get!(anchor, yaml_object, into_string, "top-right".into());
         config_get!(edge_distance, yaml_object, into_i64, 10);
         let font = Rc::new(
             Font::from_file(&font_path).chain_err(|| "Failed to load font")?,
         );
+        let icon_font = match icon_font_path {
+            Some(icon_font_path) => Some(Rc::new(
+                Font::from_file(&icon_font_path)
+                    .chain_err(|| "Failed to load icon font")?,
+            )),
+            None => None,
+        };
 
         Ok(Config {
             grid_width: grid_width as u32,
@@ -41,6 +54,8 @@ impl Config {
             grid_size: grid_size as u32,
             font,
             font_size: font_size as u32,
+            icon_font,
+            icons: Rc::new(icon_names(icon_names_path)?),
             show_duration: Duration::from_millis(
                 (show_duration_sec * 1000.0) as u64,
             ),
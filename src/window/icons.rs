@@ -0,0 +1,102 @@
+use crate::config::get_yaml;
+use crate::error::*;
+
+use std::collections::HashMap;
+
+use yaml_rust::Yaml;
+
+/// Names of commonly used FontAwesome 5 icons, and their codepoints
+const DEFAULT_ICONS: &[(&str, u32)] = &[
+    ("backward", 0xf04a),
+    ("battery-empty", 0xf244),
+    ("battery-full", 0xf240),
+    ("battery-half", 0xf242),
+    ("battery-quarter", 0xf243),
+    ("battery-three-quarters", 0xf241),
+    ("bell", 0xf0f3),
+    ("bolt", 0xf0e7),
+    ("calendar", 0xf133),
+    ("check", 0xf00c),
+    ("clock", 0xf017),
+    ("cog", 0xf013),
+    ("desktop", 0xf108),
+    ("download", 0xf019),
+    ("envelope", 0xf0e0),
+    ("exclamation-triangle", 0xf071),
+    ("file", 0xf15b),
+    ("folder", 0xf07b),
+    ("forward", 0xf04e),
+    ("hdd", 0xf0a0),
+    ("headphones", 0xf025),
+    ("home", 0xf015),
+    ("keyboard", 0xf11c),
+    ("lock", 0xf023),
+    ("memory", 0xf538),
+    ("microchip", 0xf2db),
+    ("microphone", 0xf130),
+    ("moon", 0xf186),
+    ("music", 0xf001),
+    ("network-wired", 0xf6ff),
+    ("pause", 0xf04c),
+    ("play", 0xf04b),
+    ("plug", 0xf1e6),
+    ("power-off", 0xf011),
+    ("search", 0xf002),
+    ("signal", 0xf012),
+    ("stop", 0xf04d),
+    ("sun", 0xf185),
+    ("terminal", 0xf120),
+    ("thermometer-half", 0xf2c9),
+    ("times", 0xf00d),
+    ("unlock", 0xf09c),
+    ("upload", 0xf093),
+    ("volume-down", 0xf027),
+    ("volume-mute", 0xf6a9),
+    ("volume-up", 0xf028),
+    ("wifi", 0xf1eb),
+];
+
+/// Get the table of icon names to characters. Names in the file at
+/// `icon_names_path` override the defaults
+pub fn icon_names(
+    icon_names_path: Option<String>,
+) -> Result<HashMap<String, char>> {
+    let mut icons: HashMap<String, char> = DEFAULT_ICONS
+        .iter()
+        .filter_map(|(name, codepoint)| {
+            std::char::from_u32(*codepoint).map(|c| (name.to_string(), c))
+        })
+        .collect();
+
+    if let Some(icon_names_path) = icon_names_path {
+        let yaml = get_yaml(&icon_names_path)
+            .chain_err(|| "Failed to read icon names")?;
+        let hash = yaml.into_hash().ok_or_else(|| {
+            ErrorKind::ConfigError("Icon names must be an object".into())
+        })?;
+        for (name, codepoint) in hash {
+            let name = name.into_string().ok_or_else(|| {
+                ErrorKind::ConfigError("Icon names must be strings".into())
+            })?;
+            let c = parse_codepoint(&codepoint).ok_or_else(|| {
+                ErrorKind::ConfigError(format!(
+                    "Invalid codepoint for icon {}",
+                    name
+                ))
+            })?;
+            icons.insert(name, c);
+        }
+    }
+
+    Ok(icons)
+}
+
+/// Parse a codepoint from either a hex string or an integer
+fn parse_codepoint(yaml: &Yaml) -> Option<char> {
+    let codepoint = match yaml {
+        Yaml::String(hex) => u32::from_str_radix(hex, 16).ok()?,
+        Yaml::Integer(i) => *i as u32,
+        _ => return None,
+    };
+    std::char::from_u32(codepoint)
+}
@@ -1,18 +1,24 @@
 //! Handles window setup and drawing using SFML
 
+mod click;
 mod command;
 mod config;
 mod draw;
 mod grid;
+mod icons;
+mod texture_cache;
 
 use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+pub use self::click::Click;
+use self::click::DrawnCell;
 pub use self::command::Command;
 pub use self::config::Config;
 pub use self::draw::{DrawConfig, DrawableConfig};
 use self::grid::Grid;
+pub use self::texture_cache::TextureCache;
 use crate::anchor::Anchor;
 use crate::dock::dock_window;
 use crate::error::*;
@@ -20,9 +26,10 @@ use crate::ipc;
 use crate::item::Item;
 use crate::util;
 
+use error_chain::ChainedError;
 use sfml::graphics::{
-    Color, RectangleShape, RenderStates, RenderTarget, RenderWindow, Shape,
-    Transform,
+    Color, FloatRect, IntRect, RectangleShape, RenderStates, RenderTarget,
+    RenderWindow, Shape, Transform, View,
 };
 use sfml::system::{Vector2f, Vector2i};
 use sfml::window::{Event, Key, Style, VideoMode};
@@ -37,11 +44,19 @@ pub struct Window {
     pub sfml_window: RenderWindow,
     /// Configuration for the window
     pub config: Config,
+    /// Textures for images drawn in the window
+    pub texture_cache: TextureCache,
     items: Vec<Arc<dyn Item>>,
     receive: mpsc::Receiver<Command>,
     send: mpsc::Sender<Command>,
     last_shown: Option<Instant>,
+    /// When the window last became visible after being hidden
+    visible_since: Option<Instant>,
     grid: Grid,
+    /// Cells drawn in the last frame, used to find which item was clicked
+    drawn_cells: Vec<DrawnCell>,
+    /// Index of the item currently being drawn
+    drawing_item: usize,
 }
 
 impl Window {
@@ -86,8 +101,12 @@ impl Window {
             receive,
             send,
             last_shown: None,
+            visible_since: None,
+            drawn_cells: vec![],
+            drawing_item: 0,
             grid: Grid::new(config.grid_width, config.grid_height),
             config,
+            texture_cache: TextureCache::default(),
         };
 
         window.window_loop()
@@ -104,19 +123,36 @@ impl Window {
     {
         let width = width + draw_config.horizontal_padding * 2;
         let height = height + draw_config.vertical_padding * 2;
-        let grid_width =
-            (width as f32 / self.config.grid_size as f32).ceil() as u32;
-        let grid_height =
-            (height as f32 / self.config.grid_size as f32).ceil() as u32;
+        let grid_width = ((width as f32 / self.config.grid_size as f32).ceil()
+            as u32)
+            .min(self.config.grid_width);
+        let grid_height = ((height as f32 / self.config.grid_size as f32)
+            .ceil() as u32)
+            .min(self.config.grid_height);
         let (grid_x, grid_y) = self.grid.find_space(grid_width, grid_height);
+        let index = self
+            .drawn_cells
+            .iter()
+            .filter(|cell| cell.item == self.drawing_item)
+            .count();
+        self.drawn_cells.push(DrawnCell {
+            item: self.drawing_item,
+            index,
+            rect: IntRect::new(
+                (grid_x * self.config.grid_size) as i32,
+                (grid_y * self.config.grid_size) as i32,
+                (grid_width * self.config.grid_size) as i32,
+                (grid_height * self.config.grid_size) as i32,
+            ),
+        });
 
         let x_offset = if draw_config.horizontal_centre_align {
-            ((grid_width * self.config.grid_size) - width) / 2
+            (grid_width * self.config.grid_size).saturating_sub(width) / 2
         } else {
             0
         };
         let y_offset = if draw_config.vertical_centre_align {
-            ((grid_height * self.config.grid_size) - height) / 2
+            (grid_height * self.config.grid_size).saturating_sub(height) / 2
         } else {
             0
         };
@@ -150,6 +186,26 @@ impl Window {
                 .draw_with_renderstates(&shape, render_states);
         }
 
+        // Clip the drawables to inside the border of the item
+        let window_size = self.sfml_window.size();
+        let clip = FloatRect::new(
+            (grid_x * self.config.grid_size) as f32 + BORDER_THICKNESS,
+            (grid_y * self.config.grid_size) as f32 + BORDER_THICKNESS,
+            (grid_width * self.config.grid_size) as f32
+                - BORDER_THICKNESS * 2.0,
+            (grid_height * self.config.grid_size) as f32
+                - BORDER_THICKNESS * 2.0,
+        );
+        let mut view = View::from_rect(&clip);
+        view.set_viewport(&FloatRect::new(
+            clip.left / window_size.x as f32,
+            clip.top / window_size.y as f32,
+            clip.width / window_size.x as f32,
+            clip.height / window_size.y as f32,
+        ));
+        let default_view = self.sfml_window.default_view().to_owned();
+        self.sfml_window.set_view(&view);
+
         for mut drawable_config in drawable_configs.into_iter() {
             drawable_config.render_states.transform.translate(
                 ((grid_x * self.config.grid_size)
@@ -164,6 +220,14 @@ impl Window {
                 drawable_config.render_states,
             );
         }
+        self.sfml_window.set_view(&default_view);
+    }
+
+    /// Get how long the window has been visible for
+    pub fn visible_duration(&self) -> Duration {
+        self.visible_since
+            .map(|visible_since| visible_since.elapsed())
+            .unwrap_or_default()
     }
 
     fn window_loop(&mut self) -> Result<()> {
@@ -199,6 +263,9 @@ impl Window {
             Command::Show => {
                 debug!("Showing window");
                 self.sfml_window.set_visible(true);
+                if self.last_shown.is_none() {
+                    self.visible_since = Some(Instant::now());
+                }
                 self.last_shown = Some(Instant::now());
                 let window_location = self.get_window_location();
                 self.sfml_window.set_position(&window_location);
@@ -220,11 +287,13 @@ impl Window {
                 }
                 self.sfml_window.set_visible(false);
                 self.last_shown = None;
+                self.visible_since = None;
             }
             Command::Quit => {
                 info!("Quitting due to window command");
                 return Ok(true);
             }
+            Command::Item(words) => self.item_command(&words),
         }
         Ok(false)
     }
@@ -234,8 +303,60 @@ impl Window {
             Event::Closed
             | Event::KeyPressed {
                 code: Key::Escape, ..
-            } => Ok(true),
-            _ => Ok(false),
+            } => return Ok(true),
+            Event::MouseButtonPressed { button, x, y } => {
+                if let Some(button) = click::button_number(button) {
+                    self.click(button, x, y);
+                }
+            }
+            Event::MouseWheelScrolled { wheel, delta, x, y } => {
+                self.click(click::wheel_button_number(wheel, delta), x, y);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// Send a click to the item drawn at a position in the window
+    fn click(&self, button: u32, x: i32, y: i32) {
+        let cell = match self
+            .drawn_cells
+            .iter()
+            .find(|cell| cell.rect.contains(Vector2i::new(x, y)))
+        {
+            Some(cell) => cell,
+            None => return,
+        };
+        let window_position = self.sfml_window.position();
+        let click = Click {
+            cell: cell.index,
+            button,
+            x: window_position.x + x,
+            y: window_position.y + y,
+            relative_x: x - cell.rect.left,
+            relative_y: y - cell.rect.top,
+            width: cell.rect.width,
+            height: cell.rect.height,
+        };
+        debug!("Clicked cell {} of item {}", cell.index, cell.item);
+        if let Err(err) = self.items[cell.item].click(&click) {
+            warn!("Failed to handle click: {}", err.display_chain());
+        }
+    }
+
+    /// Send a command to the items, warning if no item handles it
+    fn item_command(&self, words: &[String]) {
+        let mut handled = false;
+        for item in &self.items {
+            match item.command(words) {
+                Ok(item_handled) => handled |= item_handled,
+                Err(err) => {
+                    warn!("Failed to handle command: {}", err.display_chain())
+                }
+            }
+        }
+        if !handled {
+            warn!("No item handled command: {}", words.join(" "));
         }
     }
 
@@ -243,7 +364,9 @@ impl Window {
         trace!("Drawing window");
         self.grid = Grid::new(self.config.grid_width, self.config.grid_height);
         self.sfml_window.clear(&Color::BLACK);
-        for item in self.items.clone() {
+        self.drawn_cells.clear();
+        for (i, item) in self.items.clone().iter().enumerate() {
+            self.drawing_item = i;
             item.draw(self)?;
         }
         self.sfml_window.display();
@@ -0,0 +1,51 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use sfml::graphics::Texture;
+
+/// Cache of textures loaded from image files, keyed by path and modification
+/// time so that images are reloaded when they change
+#[derive(Default)]
+pub struct TextureCache {
+    /// Loaded textures, or `None` if the image failed to load
+    textures: HashMap<String, (SystemTime, Option<Rc<Texture>>)>,
+    /// Paths that don't exist, so that we only warn about them once
+    missing: HashSet<String>,
+}
+
+impl TextureCache {
+    /// Get the texture for an image path, or `None` if it can't be loaded
+    pub fn get(&mut self, path: &str) -> Option<Rc<Texture>> {
+        let modified = match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(err) => {
+                self.textures.remove(path);
+                if self.missing.insert(path.into()) {
+                    warn!("Failed to find image {}: {}", path, err);
+                }
+                return None;
+            }
+        };
+        self.missing.remove(path);
+
+        if let Some((cached_modified, texture)) = self.textures.get(path) {
+            if *cached_modified == modified {
+                return texture.clone();
+            }
+        }
+
+        debug!("Loading image {}", path);
+        let texture = Texture::from_file(path).map(|mut texture| {
+            texture.set_smooth(true);
+            Rc::new(texture)
+        });
+        if texture.is_none() {
+            warn!("Failed to load image {}", path);
+        }
+        self.textures
+            .insert(path.into(), (modified, texture.clone()));
+        texture
+    }
+}